@@ -0,0 +1,407 @@
+use bevy::{
+    asset::RenderAssetUsages,
+    image::{CompressedImageFormats, ImageSampler, ImageType},
+    prelude::*,
+};
+use std::collections::HashMap;
+use std::path::Path;
+
+use crate::components::Terrain;
+use crate::config::*;
+
+/// Segments of the polygons approximating ellipses
+const ELLIPSE_SEGMENTS: usize = 32;
+
+/// Static geography of the simulation, in world coordinates
+#[derive(Resource, Clone)]
+pub struct Arena {
+    /// Water area enclosed by the walls of the frame
+    pub water_area: Rect,
+    /// Basin outline, the whole water area when unset
+    pub basin: Option<Vec<Vec2>>,
+    /// Obstacles and their positions
+    pub obstacles: Vec<(Vec2, Terrain)>,
+}
+impl Arena {
+    pub fn new(data: &ArenaData) -> Result<Self, Box<dyn std::error::Error>> {
+        let water_area = Layout::new().water_area();
+        let center = water_area.center();
+        let to_world = |point: &[f32; 2]| center + Vec2::from_array(*point);
+
+        let mut obstacles = Vec::new();
+        for obstacle in &data.obstacles {
+            match obstacle {
+                ObstacleData::Rock { position, radius } => {
+                    obstacles.push((to_world(position), Terrain::Circle(*radius)));
+                }
+                ObstacleData::Island { position, radii } => {
+                    obstacles.push((
+                        to_world(position),
+                        Terrain::Polygon(ellipse(Vec2::from_array(*radii))),
+                    ));
+                }
+                ObstacleData::Reeds {
+                    position,
+                    size,
+                    spacing,
+                    stem_radius,
+                } => {
+                    let columns = (size[0] / spacing).floor() as usize + 1;
+                    let rows = (size[1] / spacing).floor() as usize + 1;
+                    let corner = to_world(position)
+                        - Vec2::new((columns - 1) as f32, (rows - 1) as f32) * *spacing / 2.0;
+                    for row in 0..rows {
+                        for column in 0..columns {
+                            let offset = Vec2::new(column as f32, row as f32) * *spacing;
+                            obstacles.push((corner + offset, Terrain::Circle(*stem_radius)));
+                        }
+                    }
+                }
+                ObstacleData::Polygon { points } => {
+                    obstacles.push((
+                        center,
+                        Terrain::Polygon(
+                            points
+                                .iter()
+                                .map(|point| Vec2::from_array(*point))
+                                .collect(),
+                        ),
+                    ));
+                }
+            }
+        }
+
+        if let Some(path) = &data.mask {
+            obstacles.extend(mask_obstacles(path, water_area)?);
+        }
+
+        Ok(Self {
+            water_area,
+            basin: data
+                .basin
+                .as_ref()
+                .map(|basin| basin.iter().map(to_world).collect()),
+            obstacles,
+        })
+    }
+
+    /// Whether a disc fits in the water, clear of the obstacles
+    pub fn contains(&self, point: Vec2, radius: f32) -> bool {
+        let inner = self.water_area.inflate(-radius);
+        if !inner.contains(point) {
+            return false;
+        }
+        if let Some(basin) = &self.basin
+            && !(point_in_polygon(point, basin) && distance_to_outline(point, basin) >= radius)
+        {
+            return false;
+        }
+        self.obstacles
+            .iter()
+            .all(|(position, terrain)| !terrain.overlaps(point - *position, radius))
+    }
+}
+
+impl Terrain {
+    /// Whether a disc, relative to the terrain position, overlaps the terrain
+    pub fn overlaps(&self, point: Vec2, radius: f32) -> bool {
+        match self {
+            Terrain::Circle(terrain_radius) => point.length() < terrain_radius + radius,
+            Terrain::Rectangle(size) => {
+                let excess = point.abs() - *size / 2.0;
+                excess.max_element() < 0.0 || excess.max(Vec2::ZERO).length() < radius
+            }
+            Terrain::Polygon(points) => {
+                point_in_polygon(point, points) || distance_to_outline(point, points) < radius
+            }
+        }
+    }
+
+    /// Mesh of the shape, relative to its position
+    pub fn mesh(&self) -> Mesh {
+        match self {
+            Terrain::Circle(radius) => Circle::new(*radius).into(),
+            Terrain::Rectangle(size) => Rectangle::from_size(*size).into(),
+            Terrain::Polygon(points) => {
+                let positions: Vec<[f32; 3]> = points
+                    .iter()
+                    .map(|point| point.extend(0.0).to_array())
+                    .collect();
+                let normals = vec![[0.0, 0.0, 1.0]; points.len()];
+                let uvs = vec![[0.0, 0.0]; points.len()];
+                Mesh::new(
+                    bevy::mesh::PrimitiveTopology::TriangleList,
+                    RenderAssetUsages::default(),
+                )
+                .with_inserted_attribute(Mesh::ATTRIBUTE_POSITION, positions)
+                .with_inserted_attribute(Mesh::ATTRIBUTE_NORMAL, normals)
+                .with_inserted_attribute(Mesh::ATTRIBUTE_UV_0, uvs)
+                .with_inserted_indices(bevy::mesh::Indices::U32(triangulate(points)))
+            }
+        }
+    }
+}
+
+/// Land of a mask image stretched over the water area, as rectangles merging the dark
+/// pixels
+fn mask_obstacles(
+    path: &Path,
+    water_area: Rect,
+) -> Result<Vec<(Vec2, Terrain)>, Box<dyn std::error::Error>> {
+    let bytes = std::fs::read(path)
+        .map_err(|e| format!("Failed to read arena mask {}: {}", path.display(), e))?;
+    let image = Image::from_buffer(
+        &bytes,
+        ImageType::Extension("png"),
+        CompressedImageFormats::NONE,
+        false,
+        ImageSampler::Default,
+        RenderAssetUsages::default(),
+    )
+    .map_err(|e| format!("Failed to decode arena mask {}: {}", path.display(), e))?;
+
+    let (width, height) = (image.width(), image.height());
+    let is_land = |x: u32, y: u32| {
+        image.get_color_at(x, y).is_ok_and(|color| {
+            let color = color.to_srgba();
+            color.alpha >= 0.5 && (color.red + color.green + color.blue) / 3.0 < 0.5
+        })
+    };
+
+    // Runs of land in each row, extended downwards while the next rows repeat them
+    let mut rectangles = Vec::new();
+    let mut open: HashMap<(u32, u32), u32> = HashMap::new();
+    for y in 0..=height {
+        let mut runs = Vec::new();
+        let mut x = 0;
+        while y < height && x < width {
+            if is_land(x, y) {
+                let start = x;
+                while x < width && is_land(x, y) {
+                    x += 1;
+                }
+                runs.push((start, x));
+            } else {
+                x += 1;
+            }
+        }
+        let mut next = HashMap::new();
+        for run in runs {
+            next.insert(run, open.remove(&run).unwrap_or(y));
+        }
+        rectangles.extend(open.drain().map(|((start, end), top)| (start, end, top, y)));
+        open = next;
+    }
+    // Stable order so that repeated loads build the same world
+    rectangles.sort_unstable();
+
+    let cell = water_area.size() / Vec2::new(width as f32, height as f32);
+    Ok(rectangles
+        .into_iter()
+        .map(|(start, end, top, bottom)| {
+            let size = Vec2::new((end - start) as f32, (bottom - top) as f32) * cell;
+            let corner = Vec2::new(
+                water_area.min.x + start as f32 * cell.x,
+                water_area.max.y - bottom as f32 * cell.y,
+            );
+            (corner + size / 2.0, Terrain::Rectangle(size))
+        })
+        .collect())
+}
+
+/// Polygon approximating an ellipse centred on the origin
+pub fn ellipse(radii: Vec2) -> Vec<Vec2> {
+    (0..ELLIPSE_SEGMENTS)
+        .map(|i| {
+            let angle = i as f32 / ELLIPSE_SEGMENTS as f32 * std::f32::consts::TAU;
+            Vec2::from_angle(angle) * radii
+        })
+        .collect()
+}
+
+/// Edges joining each point of a polygon to the next one
+pub fn outline_edges(points: &[Vec2]) -> Vec<[u32; 2]> {
+    let n = points.len() as u32;
+    (0..n).map(|i| [i, (i + 1) % n]).collect()
+}
+
+/// Even-odd rule
+pub fn point_in_polygon(point: Vec2, polygon: &[Vec2]) -> bool {
+    let mut inside = false;
+    let mut previous = polygon[polygon.len() - 1];
+    for &current in polygon {
+        if (current.y > point.y) != (previous.y > point.y)
+            && point.x
+                < (previous.x - current.x) * (point.y - current.y) / (previous.y - current.y)
+                    + current.x
+        {
+            inside = !inside;
+        }
+        previous = current;
+    }
+    inside
+}
+
+/// Distance from a point to the closest edge of a polygon
+pub fn distance_to_outline(point: Vec2, polygon: &[Vec2]) -> f32 {
+    let mut previous = polygon[polygon.len() - 1];
+    let mut distance = f32::INFINITY;
+    for &current in polygon {
+        let edge = current - previous;
+        let t = ((point - previous).dot(edge) / edge.length_squared().max(f32::EPSILON))
+            .clamp(0.0, 1.0);
+        distance = distance.min(point.distance(previous + edge * t));
+        previous = current;
+    }
+    distance
+}
+
+/// Triangle indices of a simple polygon, by ear clipping
+pub fn triangulate(polygon: &[Vec2]) -> Vec<u32> {
+    let mut remaining: Vec<usize> = (0..polygon.len()).collect();
+    // Work counter-clockwise so that ears are left turns
+    let area: f32 = (0..polygon.len())
+        .map(|i| polygon[i].perp_dot(polygon[(i + 1) % polygon.len()]))
+        .sum();
+    if area < 0.0 {
+        remaining.reverse();
+    }
+
+    let mut indices = Vec::new();
+    while remaining.len() > 3 {
+        let n = remaining.len();
+        let corners = |i: usize| {
+            (
+                remaining[(i + n - 1) % n],
+                remaining[i],
+                remaining[(i + 1) % n],
+            )
+        };
+        let ear = (0..n).find(|&i| {
+            let (a, b, c) = corners(i);
+            let (a, b, c) = (polygon[a], polygon[b], polygon[c]);
+            (b - a).perp_dot(c - b) > 0.0
+                && remaining.iter().all(|&j| {
+                    let p = polygon[j];
+                    p == a || p == b || p == c || !in_triangle(p, a, b, c)
+                })
+        });
+        // Degenerate polygons keep their last remaining points untriangulated
+        let Some(i) = ear else {
+            break;
+        };
+        let (a, b, c) = corners(i);
+        indices.extend([a as u32, b as u32, c as u32]);
+        remaining.remove(i);
+    }
+    if remaining.len() == 3 {
+        indices.extend(remaining.iter().map(|&i| i as u32));
+    }
+    indices
+}
+
+fn in_triangle(p: Vec2, a: Vec2, b: Vec2, c: Vec2) -> bool {
+    let d1 = (b - a).perp_dot(p - a);
+    let d2 = (c - b).perp_dot(p - b);
+    let d3 = (a - c).perp_dot(p - c);
+    d1 >= 0.0 && d2 >= 0.0 && d3 >= 0.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::render::render_resource::{Extent3d, TextureDimension, TextureFormat};
+
+    /// L-shaped polygon of area 3, counter-clockwise
+    fn l_shape() -> Vec<Vec2> {
+        [
+            [0.0, 0.0],
+            [2.0, 0.0],
+            [2.0, 1.0],
+            [1.0, 1.0],
+            [1.0, 2.0],
+            [0.0, 2.0],
+        ]
+        .map(Vec2::from_array)
+        .to_vec()
+    }
+
+    fn triangulated_area(polygon: &[Vec2], indices: &[u32]) -> f32 {
+        indices
+            .chunks(3)
+            .map(|triangle| {
+                let [a, b, c] = [0, 1, 2].map(|i| polygon[triangle[i] as usize]);
+                (b - a).perp_dot(c - a).abs() / 2.0
+            })
+            .sum()
+    }
+
+    #[test]
+    fn masks_merge_dark_pixels_into_rectangles() {
+        // Rows from the top: land, land, water, land and land, land, water, water
+        let (black, white) = ([0, 0, 0, 255], [255; 4]);
+        let pixels = [black, black, white, black, black, black, white, white];
+        let path = std::env::temp_dir().join("teemlabs_arena_mask.png");
+        Image::new(
+            Extent3d {
+                width: 4,
+                height: 2,
+                depth_or_array_layers: 1,
+            },
+            TextureDimension::D2,
+            pixels.concat(),
+            TextureFormat::Rgba8UnormSrgb,
+            RenderAssetUsages::default(),
+        )
+        .try_into_dynamic()
+        .unwrap()
+        .save(&path)
+        .unwrap();
+
+        let water_area = Rect::new(0.0, 0.0, 40.0, 20.0);
+        let obstacles = mask_obstacles(&path, water_area).unwrap();
+        let rectangles: Vec<(Vec2, Vec2)> = obstacles
+            .into_iter()
+            .map(|(position, terrain)| match terrain {
+                Terrain::Rectangle(size) => (position, size),
+                terrain => panic!("Unexpected mask terrain {:?}", terrain),
+            })
+            .collect();
+        assert_eq!(
+            rectangles,
+            [
+                (Vec2::new(10.0, 10.0), Vec2::new(20.0, 20.0)),
+                (Vec2::new(35.0, 15.0), Vec2::new(10.0, 10.0)),
+            ]
+        );
+        assert!(mask_obstacles(&path.with_extension("missing"), water_area).is_err());
+    }
+
+    #[test]
+    fn points_in_polygon_follow_the_even_odd_rule() {
+        let polygon = l_shape();
+        assert!(point_in_polygon(Vec2::new(0.5, 0.5), &polygon));
+        assert!(point_in_polygon(Vec2::new(1.5, 0.5), &polygon));
+        assert!(point_in_polygon(Vec2::new(0.5, 1.5), &polygon));
+        assert!(!point_in_polygon(Vec2::new(1.5, 1.5), &polygon));
+        assert!(!point_in_polygon(Vec2::new(-0.5, 0.5), &polygon));
+        assert!(!point_in_polygon(Vec2::new(0.5, 2.5), &polygon));
+    }
+
+    #[test]
+    fn triangulation_covers_the_polygon() {
+        let square = [[0.0, 0.0], [1.0, 0.0], [1.0, 1.0], [0.0, 1.0]].map(Vec2::from_array);
+        let indices = triangulate(&square);
+        assert_eq!(indices.len(), 6);
+        assert!((triangulated_area(&square, &indices) - 1.0).abs() < 1e-6);
+
+        let mut polygon = l_shape();
+        for _ in 0..2 {
+            let indices = triangulate(&polygon);
+            assert_eq!(indices.len(), 3 * (polygon.len() - 2));
+            assert!((triangulated_area(&polygon, &indices) - 3.0).abs() < 1e-6);
+            // Clockwise polygons too
+            polygon.reverse();
+        }
+    }
+}
@@ -0,0 +1,287 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::cli::BatchArgs;
+use crate::config::{OUTPUTS_DIR, RuntimeConfig};
+use crate::resources::StopReport;
+
+/// Delay between two checks of a running simulation process
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// A simulation of a batch
+#[derive(Clone)]
+pub struct BatchRun {
+    pub name: String,
+    pub config: PathBuf,
+    pub seed: u32,
+}
+
+/// Options shared by every run of a batch
+pub struct BatchOptions {
+    pub jobs: usize,
+    pub timeout: Option<Duration>,
+    pub lore: Option<PathBuf>,
+    pub duration: Option<f32>,
+    pub fps: Option<f32>,
+    pub render: bool,
+}
+
+#[derive(Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RunStatus {
+    Succeeded,
+    Failed,
+    TimedOut,
+}
+
+/// Outcome of a run, as listed in the batch index
+#[derive(Serialize, Clone)]
+pub struct RunOutcome {
+    pub name: String,
+    pub config: PathBuf,
+    pub seed: u32,
+    pub status: RunStatus,
+    pub exit_code: Option<i32>,
+    /// Wall-clock time in seconds
+    pub elapsed: f32,
+    pub stop_reason: Option<String>,
+}
+
+#[derive(Serialize)]
+struct BatchIndex<'a> {
+    succeeded: usize,
+    failed: usize,
+    timed_out: usize,
+    runs: &'a [RunOutcome],
+}
+
+/// Run every configuration once per seed
+pub fn run_batch(args: BatchArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let first_seed = args.seed.unwrap_or_else(rand::random);
+    let mut runs = Vec::new();
+    for config in &args.configs {
+        RuntimeConfig::load(config, args.lore.as_deref())
+            .and_then(|runtime_config| runtime_config.check_no_external_brain("batch runs"))
+            .map_err(|e| format!("Invalid configuration {}: {}", config.display(), e))?;
+        let stem = config
+            .file_stem()
+            .map_or("simulation".into(), |stem| stem.to_string_lossy());
+        for offset in 0..args.runs {
+            let seed = first_seed.wrapping_add(offset);
+            runs.push(BatchRun {
+                name: format!("{:03}_{}_{}", runs.len(), stem, seed),
+                config: config.clone(),
+                seed,
+            });
+        }
+    }
+
+    let batch_dir = create_batch_dir(args.output.as_deref(), "batch")?;
+    let options = BatchOptions {
+        jobs: args.jobs.unwrap_or_else(default_jobs),
+        timeout: args.timeout.map(Duration::from_secs_f64),
+        lore: args.lore,
+        duration: args.duration,
+        fps: args.fps,
+        render: args.render,
+    };
+
+    execute(runs, &batch_dir, &options)?;
+    Ok(())
+}
+
+/// Create a dated folder in the output directory
+pub fn create_batch_dir(
+    output: Option<&Path>,
+    prefix: &str,
+) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    let now: DateTime<Utc> = Utc::now();
+    let batch_dir = output.unwrap_or(Path::new(OUTPUTS_DIR)).join(
+        now.format(&format!("{}_%Y-%m-%d-%H-%M-%SZ", prefix))
+            .to_string(),
+    );
+    fs::create_dir_all(&batch_dir)
+        .map_err(|e| format!("Failed to create {}: {}", batch_dir.display(), e))?;
+    Ok(batch_dir)
+}
+
+pub fn default_jobs() -> usize {
+    thread::available_parallelism().map_or(1, |jobs| jobs.get())
+}
+
+/// Execute runs in parallel simulation processes, each writing to its own folder of
+/// `batch_dir`, then write the batch index
+pub fn execute(
+    runs: Vec<BatchRun>,
+    batch_dir: &Path,
+    options: &BatchOptions,
+) -> Result<Vec<RunOutcome>, Box<dyn std::error::Error>> {
+    let executable = std::env::current_exe()?;
+    let nb_runs = runs.len();
+    let queue = Mutex::new(runs.into_iter().enumerate().collect::<VecDeque<_>>());
+    let outcomes = Mutex::new(vec![None; nb_runs]);
+
+    println!(
+        "Running {} simulations with {} workers in {}",
+        nb_runs,
+        options.jobs,
+        batch_dir.display()
+    );
+    thread::scope(|scope| {
+        for _ in 0..options.jobs.clamp(1, nb_runs.max(1)) {
+            scope.spawn(|| {
+                loop {
+                    let Some((index, run)) = queue.lock().unwrap().pop_front() else {
+                        break;
+                    };
+                    let outcome = execute_run(&executable, &run, batch_dir, options);
+                    println!(
+                        "[{}/{}] {}: {}",
+                        index + 1,
+                        nb_runs,
+                        run.name,
+                        match outcome.status {
+                            RunStatus::Succeeded => "succeeded",
+                            RunStatus::Failed => "failed",
+                            RunStatus::TimedOut => "timed out",
+                        }
+                    );
+                    outcomes.lock().unwrap()[index] = Some(outcome);
+                }
+            });
+        }
+    });
+    let outcomes: Vec<RunOutcome> = outcomes
+        .into_inner()
+        .unwrap()
+        .into_iter()
+        .flatten()
+        .collect();
+
+    // Write the index
+    let count = |status| {
+        outcomes
+            .iter()
+            .filter(|outcome| outcome.status == status)
+            .count()
+    };
+    let index = BatchIndex {
+        succeeded: count(RunStatus::Succeeded),
+        failed: count(RunStatus::Failed),
+        timed_out: count(RunStatus::TimedOut),
+        runs: &outcomes,
+    };
+    fs::write(batch_dir.join("index.toml"), toml::to_string(&index)?)?;
+    println!(
+        "{} succeeded, {} failed, {} timed out",
+        index.succeeded, index.failed, index.timed_out
+    );
+
+    Ok(outcomes)
+}
+
+/// Run one simulation process, logging its output to its folder
+fn execute_run(
+    executable: &Path,
+    run: &BatchRun,
+    batch_dir: &Path,
+    options: &BatchOptions,
+) -> RunOutcome {
+    let started = Instant::now();
+    let run_dir = batch_dir.join(&run.name);
+    let mut outcome = RunOutcome {
+        name: run.name.clone(),
+        config: run.config.clone(),
+        seed: run.seed,
+        status: RunStatus::Failed,
+        exit_code: None,
+        elapsed: 0.0,
+        stop_reason: None,
+    };
+
+    let status = spawn_run(executable, run, batch_dir, &run_dir, options).and_then(|mut child| {
+        // Wait for the simulation, killing it once out of time
+        loop {
+            if let Some(status) = child.try_wait()? {
+                return Ok(Some(status));
+            }
+            if options
+                .timeout
+                .is_some_and(|timeout| started.elapsed() > timeout)
+            {
+                child.kill()?;
+                child.wait()?;
+                return Ok(None);
+            }
+            thread::sleep(POLL_INTERVAL);
+        }
+    });
+
+    outcome.elapsed = started.elapsed().as_secs_f32();
+    match status {
+        Ok(Some(status)) => {
+            outcome.exit_code = status.code();
+            if status.success() {
+                outcome.status = RunStatus::Succeeded;
+            }
+        }
+        Ok(None) => outcome.status = RunStatus::TimedOut,
+        Err(e) => eprintln!("Failed to run {}: {}", run.name, e),
+    }
+    outcome.stop_reason = read_stop_report(&run_dir).map(|report| report.reason);
+    outcome
+}
+
+fn spawn_run(
+    executable: &Path,
+    run: &BatchRun,
+    batch_dir: &Path,
+    run_dir: &Path,
+    options: &BatchOptions,
+) -> std::io::Result<Child> {
+    fs::create_dir_all(run_dir)?;
+    let log = File::create(run_dir.join("log.txt"))?;
+
+    let mut command = Command::new(executable);
+    command
+        .arg("run")
+        .arg("--config")
+        .arg(&run.config)
+        .arg("--seed")
+        .arg(run.seed.to_string())
+        .arg("--output")
+        .arg(batch_dir)
+        .arg("--name")
+        .arg(&run.name);
+    if let Some(lore) = &options.lore {
+        command.arg("--lore").arg(lore);
+    }
+    if let Some(duration) = options.duration {
+        command.arg("--duration").arg(duration.to_string());
+    }
+    if let Some(fps) = options.fps {
+        command.arg("--fps").arg(fps.to_string());
+    }
+    if !options.render {
+        command.arg("--headless");
+    }
+
+    command
+        .stdin(Stdio::null())
+        .stdout(log.try_clone()?)
+        .stderr(log)
+        .spawn()
+}
+
+/// Report written by the simulation when it stopped
+pub fn read_stop_report(run_dir: &Path) -> Option<StopReport> {
+    let content = fs::read_to_string(run_dir.join("stop.toml")).ok()?;
+    toml::from_str(&content).ok()
+}
@@ -0,0 +1,519 @@
+use bevy::prelude::*;
+use rand::Rng;
+
+use crate::components::*;
+use crate::config::*;
+
+/// Relation classes told apart by neural brains: food, predators, conspecifics and
+/// anything else
+const NETWORK_CLASSES: usize = 4;
+/// Inputs of neural brains, the closest sighting of each class in each sector of the
+/// vision field and the energy
+pub const NETWORK_INPUTS: usize = NETWORK_SECTORS * NETWORK_CLASSES + 1;
+
+/// Relationship of an organism with what one of its vision rays hit
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Relation {
+    Prey,
+    Predator,
+    Conspecific,
+    /// Carcass of a scavenged species
+    Carrion,
+    /// Organism of an unrelated species
+    Neutral,
+    /// Walls, terrain and inedible carcasses
+    Obstacle,
+}
+impl Relation {
+    /// Relationship of an organism of `species` with an organism of `target`
+    pub fn between(
+        species: Species,
+        hunter: Option<&Hunter>,
+        target: Species,
+        target_hunter: Option<&Hunter>,
+    ) -> Self {
+        if hunter.is_some_and(|hunter| hunter.hunts.contains(&target)) {
+            Relation::Prey
+        } else if target_hunter.is_some_and(|hunter| hunter.hunts.contains(&species)) {
+            Relation::Predator
+        } else if target == species {
+            Relation::Conspecific
+        } else {
+            Relation::Neutral
+        }
+    }
+}
+
+/// Hit of a vision ray
+pub struct Sighting {
+    /// Unit direction of the ray
+    pub direction: Vec2,
+    /// 1 at contact, 0 at the detection range
+    pub proximity: f32,
+    pub distance: f32,
+    /// Velocity of the sighted organism or carrion, zero for obstacles
+    pub velocity: Vec2,
+    pub relation: Relation,
+    /// Species of the sighted organism or carrion
+    pub species: Option<Species>,
+    /// Conspecific of a different mating type
+    pub mate: bool,
+    /// Hit entity, seen by as many sightings as rays reaching it
+    pub entity: Entity,
+}
+
+/// What a brain decides from
+pub struct Perception {
+    pub sightings: Vec<Sighting>,
+    /// Share of the maximum energy
+    pub energy: f32,
+    /// Energy above the reproduction threshold, for sexually reproducing organisms
+    pub ready_to_mate: bool,
+    /// Speed of the organism, from which prey is intercepted
+    pub speed: f32,
+    /// Seconds since birth, timing the swerves of zig-zags
+    pub age: f32,
+}
+impl Perception {
+    /// Sum of the directions of the matching sightings, weighted by the steering weights
+    /// and their proximity
+    fn steer(&self, steering: &SteeringWeights, matches: impl Fn(&Sighting) -> bool) -> Vec2 {
+        self.sightings
+            .iter()
+            .filter(|sighting| matches(sighting))
+            .map(|sighting| {
+                let weight = steering.weight(sighting, self.ready_to_mate);
+                self.aim(sighting, steering) * weight * sighting.proximity
+            })
+            .sum()
+    }
+
+    /// Direction along which the weight of a sighting applies, shaped by the hunting and
+    /// fleeing strategies. Predator weights being repulsive, fleeing toward a point aims
+    /// away from it
+    fn aim(&self, sighting: &Sighting, steering: &SteeringWeights) -> Vec2 {
+        match sighting.relation {
+            Relation::Prey => match steering.hunting {
+                HuntingStrategy::Pursuit => sighting.direction,
+                HuntingStrategy::Intercept => {
+                    let time = sighting.distance / self.speed.max(1.0);
+                    (sighting.direction * sighting.distance + sighting.velocity * time)
+                        .normalize_or(sighting.direction)
+                }
+                HuntingStrategy::Ambush if sighting.proximity >= AMBUSH_PROXIMITY => {
+                    sighting.direction
+                }
+                HuntingStrategy::Ambush => Vec2::ZERO,
+            },
+            Relation::Predator => match steering.fleeing {
+                FleeingStrategy::Away | FleeingStrategy::Burst => sighting.direction,
+                FleeingStrategy::Zigzag => {
+                    let side = if ((self.age / ZIGZAG_PERIOD) as u32).is_multiple_of(2) {
+                        1.0
+                    } else {
+                        -1.0
+                    };
+                    Vec2::from_angle(side * ZIGZAG_ANGLE.to_radians()).rotate(sighting.direction)
+                }
+                FleeingStrategy::Cover => self
+                    .sightings
+                    .iter()
+                    .filter(|cover| {
+                        matches!(cover.relation, Relation::Obstacle | Relation::Neutral)
+                            && cover.direction.dot(sighting.direction) < 0.0
+                    })
+                    .max_by(|a, b| a.proximity.total_cmp(&b.proximity))
+                    .map_or(sighting.direction, |cover| -cover.direction),
+            },
+            _ => sighting.direction,
+        }
+    }
+
+    /// Boids steering among the sighted conspecifics, away from the ones closer than the
+    /// spacing, along their mean heading and toward their centre. Each term is a mean over
+    /// the neighbours, so that it is at most its weight however large the school
+    fn school(&self, steering: &SteeringWeights) -> Vec2 {
+        let Some(schooling) = steering.schooling else {
+            return Vec2::ZERO;
+        };
+        // Conspecifics count once, at their closest point, however many rays reach them
+        let mut neighbours: Vec<&Sighting> = self
+            .sightings
+            .iter()
+            .filter(|sighting| sighting.relation == Relation::Conspecific)
+            .collect();
+        neighbours.sort_by(|neighbour1, neighbour2| {
+            neighbour1
+                .entity
+                .cmp(&neighbour2.entity)
+                .then(neighbour1.distance.total_cmp(&neighbour2.distance))
+        });
+        neighbours.dedup_by_key(|neighbour| neighbour.entity);
+        if neighbours.is_empty() {
+            return Vec2::ZERO;
+        }
+
+        let count = neighbours.len() as f32;
+        let separation = neighbours
+            .iter()
+            .filter(|neighbour| neighbour.distance < schooling.spacing)
+            .map(|neighbour| -neighbour.direction * (1.0 - neighbour.distance / schooling.spacing))
+            .sum::<Vec2>()
+            / count;
+        let alignment = neighbours
+            .iter()
+            .map(|neighbour| neighbour.velocity.normalize_or_zero())
+            .sum::<Vec2>()
+            / count;
+        let cohesion = neighbours
+            .iter()
+            .map(|neighbour| neighbour.direction)
+            .sum::<Vec2>()
+            / count;
+        separation * schooling.separation
+            + alignment * schooling.alignment
+            + cohesion * schooling.cohesion
+    }
+
+    /// Whether a predator is close enough for a jet burst
+    pub fn bursts(&self, steering: &SteeringWeights) -> bool {
+        steering.fleeing == FleeingStrategy::Burst
+            && self.sightings.iter().any(|sighting| {
+                sighting.relation == Relation::Predator && sighting.proximity >= BURST_PROXIMITY
+            })
+    }
+
+    fn sees(&self, relation: Relation) -> bool {
+        self.sightings
+            .iter()
+            .any(|sighting| sighting.relation == relation)
+    }
+
+    /// Inputs of a neural brain. Sectors divide the directions around the organism
+    /// starting from the positive x axis
+    fn network_inputs(&self) -> [f32; NETWORK_INPUTS] {
+        let mut inputs = [0.0_f32; NETWORK_INPUTS];
+        for sighting in &self.sightings {
+            let angle = sighting
+                .direction
+                .to_angle()
+                .rem_euclid(std::f32::consts::TAU);
+            let sector = ((angle / std::f32::consts::TAU * NETWORK_SECTORS as f32) as usize)
+                .min(NETWORK_SECTORS - 1);
+            let class = match sighting.relation {
+                Relation::Prey | Relation::Carrion => 0,
+                Relation::Predator => 1,
+                Relation::Conspecific => 2,
+                Relation::Neutral | Relation::Obstacle => 3,
+            };
+            let input = &mut inputs[sector * NETWORK_CLASSES + class];
+            *input = input.max(sighting.proximity);
+        }
+        inputs[NETWORK_INPUTS - 1] = self.energy;
+        inputs
+    }
+}
+
+impl Network {
+    /// Desired force, each component in [-1, 1]
+    pub fn forward(&self, inputs: &[f32; NETWORK_INPUTS]) -> Vec2 {
+        let (hidden_weights, output_weights) =
+            self.weights.split_at(self.hidden * (NETWORK_INPUTS + 1));
+        let hidden: Vec<f32> = hidden_weights
+            .chunks_exact(NETWORK_INPUTS + 1)
+            .map(|unit| {
+                let sum: f32 = unit.iter().zip(inputs).map(|(w, x)| w * x).sum();
+                (sum + unit[NETWORK_INPUTS]).tanh()
+            })
+            .collect();
+        let mut outputs = output_weights.chunks_exact(self.hidden + 1).map(|unit| {
+            let sum: f32 = unit.iter().zip(&hidden).map(|(w, x)| w * x).sum();
+            (sum + unit[self.hidden]).tanh()
+        });
+        Vec2::new(
+            outputs.next().unwrap_or_default(),
+            outputs.next().unwrap_or_default(),
+        )
+    }
+}
+
+impl Brain {
+    /// Desired force of an organism. External brains are driven through `ExternalIntents`
+    /// and decide nothing by themselves
+    pub fn decide(
+        &mut self,
+        perception: &Perception,
+        steering: &SteeringWeights,
+        time_step: f32,
+        rng: &mut impl Rng,
+    ) -> Vec2 {
+        match self {
+            Brain::Weighted => perception.steer(steering, |_| true) + perception.school(steering),
+            Brain::External => Vec2::ZERO,
+            Brain::Behaviour {
+                params,
+                state,
+                elapsed,
+                heading,
+            } => {
+                *elapsed += time_step;
+
+                // Transitions, from the most to the least urgent
+                let threatened = perception.sees(Relation::Predator);
+                let food = perception.sees(Relation::Prey) || perception.sees(Relation::Carrion);
+                let mate = perception.ready_to_mate
+                    && perception.sightings.iter().any(|sighting| sighting.mate);
+                let hungry = perception.energy < params.hunger
+                    || (*state == BehaviourState::Forage && perception.energy < params.satiety);
+                let next = if threatened
+                    || (*state == BehaviourState::Flee && *elapsed < params.calm_time)
+                {
+                    BehaviourState::Flee
+                } else if mate {
+                    BehaviourState::SeekMate
+                } else if food && hungry {
+                    BehaviourState::Forage
+                } else if (*state == BehaviourState::Rest && *elapsed < params.rest_time)
+                    || (*state == BehaviourState::Forage && perception.energy >= params.satiety)
+                {
+                    BehaviourState::Rest
+                } else {
+                    BehaviourState::Wander
+                };
+                // Fleeing lasts until predators have been out of sight for a while
+                if next != *state || threatened {
+                    *state = next;
+                    *elapsed = 0.0;
+                }
+
+                // Schools hold together in every state but rest
+                let school = perception.school(steering);
+                match state {
+                    BehaviourState::Flee => {
+                        // Keep running the same way once predators are out of sight
+                        let away = perception
+                            .steer(steering, |sighting| sighting.relation == Relation::Predator);
+                        *heading = away.normalize_or(*heading);
+                        let flight = if threatened { away } else { *heading };
+                        flight + school
+                    }
+                    BehaviourState::SeekMate => {
+                        perception.steer(steering, |sighting| sighting.mate) + school
+                    }
+                    BehaviourState::Forage => {
+                        perception.steer(steering, |sighting| {
+                            matches!(sighting.relation, Relation::Prey | Relation::Carrion)
+                        }) + school
+                    }
+                    BehaviourState::Rest => Vec2::ZERO,
+                    BehaviourState::Wander => {
+                        if *heading == Vec2::ZERO {
+                            *heading =
+                                Vec2::from_angle(rng.random_range(0.0..std::f32::consts::TAU));
+                        } else if *elapsed >= params.wander_interval {
+                            let turn = rng.random_range(-params.wander_turn..=params.wander_turn);
+                            *heading = Vec2::from_angle(turn).rotate(*heading);
+                            *elapsed = 0.0;
+                        }
+                        *heading + school
+                    }
+                }
+            }
+            Brain::Neural { network, .. } => network.forward(&perception.network_inputs()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+
+    fn sighting(relation: Relation, direction: Vec2) -> Sighting {
+        Sighting {
+            direction,
+            proximity: 0.5,
+            distance: 50.0,
+            velocity: Vec2::ZERO,
+            relation,
+            species: None,
+            mate: false,
+            entity: Entity::PLACEHOLDER,
+        }
+    }
+
+    fn conspecific(entity: Entity, direction: Vec2, distance: f32) -> Sighting {
+        Sighting {
+            direction,
+            proximity: 1.0 - distance / 100.0,
+            distance,
+            velocity: Vec2::X,
+            relation: Relation::Conspecific,
+            species: None,
+            mate: false,
+            entity,
+        }
+    }
+
+    fn perception(sightings: Vec<Sighting>, energy: f32) -> Perception {
+        Perception {
+            sightings,
+            energy,
+            ready_to_mate: false,
+            speed: 0.0,
+            age: 0.0,
+        }
+    }
+
+    fn schooling() -> SteeringWeights {
+        let mut steering = SteeringWeights::new(1.0, -1.0, 1.0, 0.0, 0.0);
+        steering.schooling = Some(Schooling {
+            separation: 2.0,
+            alignment: 1.0,
+            cohesion: 1.0,
+            spacing: 20.0,
+        });
+        steering
+    }
+
+    /// Behaviour brain starting to wander, resting for 2 seconds and calming down after 1
+    fn behaviour() -> Brain {
+        Brain::Behaviour {
+            params: BehaviourParams {
+                hunger: 0.5,
+                satiety: 0.9,
+                rest_time: 2.0,
+                calm_time: 1.0,
+                wander_interval: 1.0,
+                wander_turn: 0.5,
+            },
+            state: BehaviourState::Wander,
+            elapsed: 0.0,
+            heading: Vec2::ZERO,
+        }
+    }
+
+    fn state(brain: &Brain) -> BehaviourState {
+        match brain {
+            Brain::Behaviour { state, .. } => *state,
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn behaviours_follow_their_transitions() {
+        let steering = SteeringWeights::new(1.0, -1.0, 0.0, 0.0, 1.0);
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        let mut brain = behaviour();
+        let mut decide = |brain: &mut Brain, sightings: Vec<Sighting>, energy: f32| {
+            brain.decide(&perception(sightings, energy), &steering, 0.1, &mut rng)
+        };
+
+        // Nothing in sight, wander along a random heading
+        let force = decide(&mut brain, Vec::new(), 1.0);
+        assert_eq!(state(&brain), BehaviourState::Wander);
+        assert!((force.length() - 1.0).abs() < 1e-5);
+
+        // Hungry and seeing prey, forage toward it
+        let force = decide(&mut brain, vec![sighting(Relation::Prey, Vec2::X)], 0.3);
+        assert_eq!(state(&brain), BehaviourState::Forage);
+        assert!(force.x > 0.0);
+
+        // Sated, rest in place
+        let force = decide(&mut brain, vec![sighting(Relation::Prey, Vec2::X)], 0.95);
+        assert_eq!(state(&brain), BehaviourState::Rest);
+        assert_eq!(force, Vec2::ZERO);
+
+        // Threatened, flee away from the predator
+        let force = decide(
+            &mut brain,
+            vec![sighting(Relation::Predator, Vec2::Y)],
+            0.95,
+        );
+        assert_eq!(state(&brain), BehaviourState::Flee);
+        assert!(force.y < 0.0);
+
+        // Fleeing lasts until the predator has been out of sight for the calm time, in
+        // the last direction away from it
+        let force = decide(&mut brain, Vec::new(), 0.95);
+        assert_eq!(state(&brain), BehaviourState::Flee);
+        assert!(force.distance(Vec2::NEG_Y) < 1e-5);
+        for _ in 0..10 {
+            decide(&mut brain, Vec::new(), 0.95);
+        }
+        assert_eq!(state(&brain), BehaviourState::Wander);
+    }
+
+    #[test]
+    fn organisms_ready_to_mate_seek_mates() {
+        let steering = SteeringWeights::new(1.0, -1.0, 0.0, 0.0, 1.0);
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        let mut brain = behaviour();
+        let mut mate = sighting(Relation::Conspecific, Vec2::NEG_X);
+        mate.mate = true;
+        let mut perception = perception(vec![mate], 1.0);
+
+        brain.decide(&perception, &steering, 0.1, &mut rng);
+        assert_eq!(state(&brain), BehaviourState::Wander);
+        perception.ready_to_mate = true;
+        let force = brain.decide(&perception, &steering, 0.1, &mut rng);
+        assert_eq!(state(&brain), BehaviourState::SeekMate);
+        assert!(force.x < 0.0);
+    }
+
+    #[test]
+    fn schools_count_each_neighbour_once() {
+        let near = Entity::from_raw_u32(1).unwrap();
+        let far = Entity::from_raw_u32(2).unwrap();
+        let once = perception(
+            vec![
+                conspecific(near, Vec2::Y, 10.0),
+                conspecific(far, Vec2::X, 50.0),
+            ],
+            1.0,
+        );
+        let rays = perception(
+            vec![
+                conspecific(near, Vec2::Y, 10.0),
+                conspecific(near, Vec2::from_angle(1.6), 11.0),
+                conspecific(near, Vec2::from_angle(1.5), 12.0),
+                conspecific(far, Vec2::X, 50.0),
+            ],
+            1.0,
+        );
+        let steering = schooling();
+        assert!(once.school(&steering).distance(rays.school(&steering)) < 1e-6);
+    }
+
+    #[test]
+    fn schooling_terms_stay_within_their_weights() {
+        let school: Vec<Sighting> = (1..=100)
+            .map(|index| conspecific(Entity::from_raw_u32(index).unwrap(), Vec2::X, 50.0))
+            .collect();
+        // Alignment and cohesion both pull along x, at their unit weights
+        let force = perception(school, 1.0).school(&schooling());
+        assert!(force.distance(Vec2::new(2.0, 0.0)) < 1e-5);
+    }
+
+    #[test]
+    fn single_prey_among_many_obstacle_rays_is_pursued() {
+        let mut prey = sighting(Relation::Prey, Vec2::X);
+        prey.proximity = 0.2;
+        let mut sightings: Vec<Sighting> = (1..360)
+            .map(|degree| {
+                sighting(
+                    Relation::Obstacle,
+                    Vec2::from_angle((degree as f32).to_radians()),
+                )
+            })
+            .collect();
+        sightings.push(prey);
+        let steering = SteeringWeights::new(1.0, -1.0, 0.0, 0.0, 1.0);
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        let force = Brain::Weighted.decide(&perception(sightings, 1.0), &steering, 0.1, &mut rng);
+        // Over the movement threshold of the decision system
+        assert!(force.length_squared() > 0.01);
+        assert!(force.x > 0.0);
+    }
+}
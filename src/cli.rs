@@ -0,0 +1,200 @@
+use clap::{Args, Parser, Subcommand};
+use std::path::PathBuf;
+
+/// Default simulation configuration path
+pub const DEFAULT_CONFIG_PATH: &str = "config/simulation.toml";
+
+/// TeemLabs ecosystem simulation engine
+#[derive(Parser)]
+#[command(version, about, args_conflicts_with_subcommands = true)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub action: Option<Action>,
+
+    /// Arguments of the default `run` command
+    #[command(flatten)]
+    pub run: RunArgs,
+}
+
+#[derive(Subcommand)]
+pub enum Action {
+    /// Run a simulation and capture it to video or statistics (default)
+    Run(RunArgs),
+    /// Load and validate the simulation and lore configuration
+    Validate(ConfigArgs),
+    /// List the biomes defined in the lore
+    ListBiomes(LoreArgs),
+    /// Run several simulations in parallel processes
+    Batch(BatchArgs),
+    /// Render a replay recorded during a headless run to video
+    Replay(ReplayArgs),
+    /// Sweep parameters of a simulation configuration and tabulate the results
+    Experiment(ExperimentArgs),
+}
+
+/// Configuration files
+#[derive(Args, Clone)]
+pub struct ConfigArgs {
+    /// Simulation configuration file
+    #[arg(short, long, default_value = DEFAULT_CONFIG_PATH)]
+    pub config: PathBuf,
+
+    /// Lore file or directory, overrides the `lore` key of the simulation configuration
+    #[arg(short, long)]
+    pub lore: Option<PathBuf>,
+}
+
+/// Lore source
+#[derive(Args, Clone)]
+pub struct LoreArgs {
+    /// Lore file or directory, the embedded lore is used when omitted
+    #[arg(short, long)]
+    pub lore: Option<PathBuf>,
+}
+
+/// Simulation run overrides, taking precedence over the simulation configuration
+#[derive(Args, Clone)]
+pub struct RunArgs {
+    #[command(flatten)]
+    pub config: ConfigArgs,
+
+    /// Directory in which the dated simulation folder is created
+    #[arg(short, long)]
+    pub output: Option<PathBuf>,
+
+    /// Name of the simulation folder, replacing the dated name
+    #[arg(short, long)]
+    pub name: Option<String>,
+
+    /// Random number generator seed
+    #[arg(short, long)]
+    pub seed: Option<u32>,
+
+    /// Maximum simulated duration in seconds, overrides `stop.max_duration`
+    #[arg(short, long)]
+    pub duration: Option<f32>,
+
+    /// Frame rate of the simulation and of the captured video
+    #[arg(short, long)]
+    pub fps: Option<f32>,
+
+    /// Run the simulation only, without rendering nor video capture
+    #[arg(long)]
+    pub headless: bool,
+
+    /// Do not record a replay of headless runs
+    #[arg(long)]
+    pub no_replay: bool,
+
+    /// Simulated times in seconds at which world snapshots are saved
+    #[arg(long, value_delimiter = ',')]
+    pub snapshot_at: Vec<f32>,
+
+    /// Start from a world snapshot instead of the configured populations. The random
+    /// streams are resumed unless a seed is given. Durations still count from the start
+    /// of the original run
+    #[arg(long)]
+    pub restore: Option<PathBuf>,
+}
+
+/// Batch of simulations
+#[derive(Args, Clone)]
+pub struct BatchArgs {
+    /// Simulation configuration files, each run once per seed
+    #[arg(default_value = DEFAULT_CONFIG_PATH)]
+    pub configs: Vec<PathBuf>,
+
+    /// Lore file or directory, overrides the `lore` key of the simulation configurations
+    #[arg(short, long)]
+    pub lore: Option<PathBuf>,
+
+    /// Number of runs per configuration, with consecutive seeds
+    #[arg(short = 'n', long, default_value_t = 1)]
+    pub runs: u32,
+
+    /// Seed of the first run of each configuration
+    #[arg(short, long)]
+    pub seed: Option<u32>,
+
+    /// Number of simulations run at once, defaults to the number of CPUs
+    #[arg(short, long)]
+    pub jobs: Option<usize>,
+
+    /// Wall-clock time limit of each run in seconds
+    #[arg(short, long)]
+    pub timeout: Option<f64>,
+
+    /// Directory in which the dated batch folder is created
+    #[arg(short, long)]
+    pub output: Option<PathBuf>,
+
+    /// Maximum simulated duration in seconds, overrides `stop.max_duration`
+    #[arg(short, long)]
+    pub duration: Option<f32>,
+
+    /// Frame rate of the simulations
+    #[arg(short, long)]
+    pub fps: Option<f32>,
+
+    /// Render and capture every run to video instead of running headless
+    #[arg(long)]
+    pub render: bool,
+}
+
+/// Parameter sweep
+#[derive(Args, Clone)]
+pub struct ExperimentArgs {
+    /// Experiment file declaring the base configuration and the swept axes
+    pub experiment: PathBuf,
+
+    /// Lore file or directory, overrides the `lore` key of the base configuration
+    #[arg(short, long)]
+    pub lore: Option<PathBuf>,
+
+    /// Number of simulations run at once, defaults to the number of CPUs
+    #[arg(short, long)]
+    pub jobs: Option<usize>,
+
+    /// Wall-clock time limit of each run in seconds
+    #[arg(short, long)]
+    pub timeout: Option<f64>,
+
+    /// Directory in which the dated experiment folder is created
+    #[arg(short, long)]
+    pub output: Option<PathBuf>,
+
+    /// Maximum simulated duration in seconds, overrides `stop.max_duration`
+    #[arg(short, long)]
+    pub duration: Option<f32>,
+
+    /// Frame rate of the simulations
+    #[arg(short, long)]
+    pub fps: Option<f32>,
+
+    /// Render and capture every run to video instead of running headless
+    #[arg(long)]
+    pub render: bool,
+
+    /// Write and validate the expanded configurations without running them
+    #[arg(long)]
+    pub dry_run: bool,
+}
+
+/// Replay rendering
+#[derive(Args, Clone)]
+pub struct ReplayArgs {
+    /// Replay file recorded by a headless run
+    pub replay: PathBuf,
+
+    /// Configuration of the recorded run
+    #[command(flatten)]
+    pub config: ConfigArgs,
+
+    /// Directory in which the video folder is created, defaults to the replay folder
+    #[arg(short, long)]
+    pub output: Option<PathBuf>,
+
+    /// Name of the video, defaults to the name of the replay folder
+    #[arg(short, long)]
+    pub name: Option<String>,
+}
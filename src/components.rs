@@ -1,79 +1,51 @@
-use crate::config::RuntimeConfig;
+use crate::brain::{NETWORK_INPUTS, Relation, Sighting};
+use crate::config::{FleeingStrategy, HuntingStrategy, RuntimeConfig};
 use bevy::prelude::*;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 
-#[derive(Component, Debug, PartialEq, Eq, Clone, Copy)]
-pub enum Species {
-    // Plants
-    Sahlalga,
-    Mirajun,
-    Lodril,
-    // Prey
-    Dunetide,
-    Vanyr,
-    // Predators
-    Gharlox,
-    Thalvyrn,
-    // Apex predators
-    Hakursa,
-    Myrrkul,
-    // Ezerast - Salt marsh
-    Qyrsel,
-    Ozyrae,
-    Veytris,
-    Brisqal,
-    Chalyth,
-    Vorqualis,
-    // Irr'Umar - Swamp
-    Vyrmosa,
-    Qirval,
-    Lorynth,
-    Dravym,
-    Zyralith,
-    // Onomora - Thermal spring basin
-    Lyrvane,
-    Omyra,
-    Cindralys,
-    Pyrralis,
-    Onytheron,
+/// Species identity, interned from the lore keys of the current biome
+#[derive(Component, Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub struct Species(u16);
+impl Species {
+    /// Dense index, in `0..SpeciesRegistry::len()`
+    pub fn index(&self) -> usize {
+        self.0 as usize
+    }
 }
 
-impl Species {
-    pub fn from_string(s: &str) -> Option<Self> {
-        match s.to_lowercase().as_str() {
-            // Plants
-            "sahlalga" => Some(Species::Sahlalga),
-            "mirajun" => Some(Species::Mirajun),
-            "lodril" => Some(Species::Lodril),
-            // Prey
-            "dunetide" => Some(Species::Dunetide),
-            "vanyr" => Some(Species::Vanyr),
-            // Predators
-            "gharlox" => Some(Species::Gharlox),
-            "thalvyrn" => Some(Species::Thalvyrn),
-            // Apex predators
-            "hakursa" => Some(Species::Hakursa),
-            "myrrkul" => Some(Species::Myrrkul),
-            // Ezerast - Salt marsh
-            "qyrsel" => Some(Species::Qyrsel),
-            "ozyrae" => Some(Species::Ozyrae),
-            "veytris" => Some(Species::Veytris),
-            "brisqal" => Some(Species::Brisqal),
-            "chalyth" => Some(Species::Chalyth),
-            "vorqualis" => Some(Species::Vorqualis),
-            // Irr'Umar - Swamp
-            "vyrmosa" => Some(Species::Vyrmosa),
-            "qirval" => Some(Species::Qirval),
-            "lorynth" => Some(Species::Lorynth),
-            "dravym" => Some(Species::Dravym),
-            "zyralith" => Some(Species::Zyralith),
-            // Onomora - Thermal spring basin
-            "lyrvane" => Some(Species::Lyrvane),
-            "omyra" => Some(Species::Omyra),
-            "cindralys" => Some(Species::Cindralys),
-            "pyrralis" => Some(Species::Pyrralis),
-            "onytheron" => Some(Species::Onytheron),
-            _ => None,
+/// Registry mapping lore species keys to their interned `Species` ids
+#[derive(Resource, Debug, Clone, Default)]
+pub struct SpeciesRegistry {
+    keys: Vec<String>,
+    ids: HashMap<String, Species>,
+}
+impl SpeciesRegistry {
+    pub fn register(&mut self, key: &str) -> Species {
+        if let Some(species) = self.ids.get(key) {
+            return *species;
         }
+        let species = Species(self.keys.len() as u16);
+        self.keys.push(key.to_string());
+        self.ids.insert(key.to_string(), species);
+        species
+    }
+
+    pub fn get(&self, key: &str) -> Option<Species> {
+        self.ids.get(key).copied()
+    }
+
+    pub fn key(&self, species: Species) -> &str {
+        &self.keys[species.index()]
+    }
+
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = Species> + '_ {
+        (0..self.keys.len()).map(|index| Species(index as u16))
     }
 }
 
@@ -118,7 +90,280 @@ pub struct RayHitInfo {
 pub struct MovementIntent {
     pub desired_direction: Vec2,
     pub desired_force: Vec2,
+    /// Jet burst away from a close predator, multiplying the acceleration
+    pub burst: bool,
+}
+
+#[derive(Component, Clone)]
+pub struct Locomotion {
+    pub turn_responsiveness: f32,
+    pub acceleration: f32,
+}
+impl Locomotion {
+    pub fn new(turn_responsiveness: f32, acceleration: f32) -> Self {
+        Self {
+            turn_responsiveness,
+            acceleration,
+        }
+    }
+}
+
+/// Steering weights given to vision hits depending on the relationship with the hit species
+#[derive(Component, Clone)]
+pub struct SteeringWeights {
+    pub prey: f32,
+    pub predator: f32,
+    pub conspecific: f32,
+    pub neutral: f32,
+    /// Attraction toward compatible mates, when ready to mate
+    pub mate: f32,
+    pub overrides: HashMap<Species, f32>,
+    pub hunting: HuntingStrategy,
+    pub fleeing: FleeingStrategy,
+    /// Boids steering among sighted conspecifics
+    pub schooling: Option<Schooling>,
+}
+impl SteeringWeights {
+    pub fn new(prey: f32, predator: f32, conspecific: f32, neutral: f32, mate: f32) -> Self {
+        Self {
+            prey,
+            predator,
+            conspecific,
+            neutral,
+            mate,
+            overrides: HashMap::new(),
+            hunting: HuntingStrategy::default(),
+            fleeing: FleeingStrategy::default(),
+            schooling: None,
+        }
+    }
+
+    pub fn with_strategies(mut self, hunting: HuntingStrategy, fleeing: FleeingStrategy) -> Self {
+        self.hunting = hunting;
+        self.fleeing = fleeing;
+        self
+    }
+
+    pub fn with_schooling(mut self, schooling: Option<Schooling>) -> Self {
+        self.schooling = schooling;
+        self
+    }
+
+    pub fn with_override(mut self, target: Species, weight: f32) -> Self {
+        self.overrides.insert(target, weight);
+        self
+    }
+
+    /// Weight of a sighting, per-species overrides taking precedence over the
+    /// relationship with organisms
+    pub fn weight(&self, sighting: &Sighting, ready_to_mate: bool) -> f32 {
+        if ready_to_mate && sighting.mate {
+            return self.mate;
+        }
+        let relation_weight = match sighting.relation {
+            // Carrion is as attractive as prey
+            Relation::Carrion => return self.prey,
+            Relation::Obstacle => return self.neutral,
+            Relation::Prey => self.prey,
+            Relation::Predator => self.predator,
+            Relation::Conspecific => self.conspecific,
+            Relation::Neutral => self.neutral,
+        };
+        sighting
+            .species
+            .and_then(|species| self.overrides.get(&species).copied())
+            .unwrap_or(relation_weight)
+    }
+}
+
+/// Per-species weights of the separation, alignment and cohesion of schools
+#[derive(Clone, Copy, Debug)]
+pub struct Schooling {
+    pub separation: f32,
+    pub alignment: f32,
+    pub cohesion: f32,
+    /// Distance kept from conspecifics, in pixels
+    pub spacing: f32,
+}
+
+/// Decision model turning what an organism perceives into its movement intent
+#[derive(Component, Clone, Debug)]
+pub enum Brain {
+    /// Sum of the sighting directions, weighted by the steering weights
+    Weighted,
+    /// Driven from outside the simulation through `ExternalIntents`
+    External,
+    /// State machine steering toward the sightings relevant to its current state
+    Behaviour {
+        params: BehaviourParams,
+        state: BehaviourState,
+        /// Seconds since the state was entered, or since the last heading change when
+        /// wandering
+        elapsed: f32,
+        /// Wandering direction, or last direction away from predators when fleeing,
+        /// unset until the first wander or flight
+        heading: Vec2,
+    },
+    /// Feed-forward network from the sectors of the vision field and the energy
+    Neural {
+        network: Network,
+        /// Probability that each weight mutates when a child is born
+        mutation_rate: f32,
+        /// Largest change of a mutated weight
+        mutation_strength: f32,
+    },
 }
+impl Brain {
+    pub fn behaviour(params: BehaviourParams) -> Self {
+        Brain::Behaviour {
+            params,
+            state: BehaviourState::Wander,
+            elapsed: 0.0,
+            heading: Vec2::ZERO,
+        }
+    }
+
+    /// Brain of a newborn, with the same model and a fresh state
+    pub fn newborn(&self) -> Self {
+        match self {
+            Brain::Behaviour { params, .. } => Brain::behaviour(*params),
+            brain => brain.clone(),
+        }
+    }
+
+    pub fn network(&self) -> Option<&Network> {
+        match self {
+            Brain::Neural { network, .. } => Some(network),
+            _ => None,
+        }
+    }
+
+    /// Same brain deciding with another network, for neural brains
+    pub fn with_network(&self, network: Network) -> Self {
+        match self {
+            Brain::Neural {
+                mutation_rate,
+                mutation_strength,
+                ..
+            } => Brain::Neural {
+                network,
+                mutation_rate: *mutation_rate,
+                mutation_strength: *mutation_strength,
+            },
+            brain => brain.clone(),
+        }
+    }
+
+    /// Brain taking each network weight from either parent, for neural brains
+    pub fn recombined(&self, other: &Brain, rng: &mut impl Rng) -> Self {
+        match (self.network(), other.network()) {
+            (Some(network), Some(other)) => self.with_network(network.recombined(other, rng)),
+            _ => self.clone(),
+        }
+    }
+
+    /// Copy of the brain whose network weights mutate with the species mutation rate
+    pub fn mutated(&self, rng: &mut impl Rng) -> Self {
+        match self {
+            Brain::Neural {
+                network,
+                mutation_rate,
+                mutation_strength,
+            } => self.with_network(network.mutated(*mutation_rate, *mutation_strength, rng)),
+            brain => brain.clone(),
+        }
+    }
+}
+
+/// Weights of a network with one hidden layer. Each hidden unit has a weight per input
+/// and a bias, followed by a weight per hidden unit and a bias for each of the two outputs
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Network {
+    pub hidden: usize,
+    pub weights: Vec<f32>,
+}
+impl Network {
+    /// Number of weights of a network with `hidden` units
+    pub fn size(hidden: usize) -> usize {
+        hidden * (NETWORK_INPUTS + 1) + 2 * (hidden + 1)
+    }
+
+    /// Network deciding nothing, until weights are drawn for it
+    pub fn zeroed(hidden: usize) -> Self {
+        Self {
+            hidden,
+            weights: vec![0.0; Self::size(hidden)],
+        }
+    }
+
+    pub fn random(hidden: usize, rng: &mut impl Rng) -> Self {
+        Self {
+            hidden,
+            weights: (0..Self::size(hidden))
+                .map(|_| rng.random_range(-1.0..=1.0))
+                .collect(),
+        }
+    }
+
+    /// Whether the weights match the shape and are finite
+    pub fn is_valid(&self) -> bool {
+        self.weights.len() == Self::size(self.hidden)
+            && self.weights.iter().all(|weight| weight.is_finite())
+    }
+
+    /// Network taking each weight from either parent, or the first parent's network when
+    /// the shapes differ
+    pub fn recombined(&self, other: &Network, rng: &mut impl Rng) -> Self {
+        let mut network = self.clone();
+        if other.hidden == self.hidden {
+            for (weight, other) in network.weights.iter_mut().zip(&other.weights) {
+                if rng.random_bool(0.5) {
+                    *weight = *other;
+                }
+            }
+        }
+        network
+    }
+
+    pub fn mutated(&self, rate: f32, strength: f32, rng: &mut impl Rng) -> Self {
+        let mut network = self.clone();
+        if rate <= 0.0 {
+            return network;
+        }
+        for weight in &mut network.weights {
+            if rng.random::<f32>() < rate {
+                *weight += strength * rng.random_range(-1.0..=1.0);
+            }
+        }
+        network
+    }
+}
+
+/// Per-species transition parameters of the behaviour state machine
+#[derive(Clone, Copy, Debug)]
+pub struct BehaviourParams {
+    pub hunger: f32,
+    pub satiety: f32,
+    pub rest_time: f32,
+    pub calm_time: f32,
+    pub wander_interval: f32,
+    /// Largest change of the wandering heading, in radians
+    pub wander_turn: f32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BehaviourState {
+    Wander,
+    Forage,
+    Flee,
+    Rest,
+    SeekMate,
+}
+
+/// Seconds since birth
+#[derive(Component, Clone, Copy, Default)]
+pub struct Age(pub f32);
 
 /// Traits
 #[derive(Component, Clone)]
@@ -131,6 +376,17 @@ impl Hunter {
     }
 }
 
+/// Species whose carcasses are eaten
+#[derive(Component, Clone)]
+pub struct Scavenger {
+    pub scavenges: Vec<Species>,
+}
+impl Scavenger {
+    pub fn new(scavenges: Vec<Species>) -> Self {
+        Self { scavenges }
+    }
+}
+
 #[derive(Component, Clone)]
 pub struct Speed(pub f32);
 impl Speed {
@@ -196,6 +452,15 @@ impl EntityColor {
 #[derive(Component, Clone)]
 pub struct Consumable;
 
+/// Remains of an organism of the given species, decaying until eaten or gone
+#[derive(Component, Clone, Copy)]
+pub struct Carcass {
+    pub species: Species,
+}
+
+/// Query filter matching organisms and carcasses
+pub type OrganismOrCarcass = Or<(With<Species>, With<Carcass>)>;
+
 #[derive(Component, Clone)]
 pub struct ActiveMover;
 
@@ -211,6 +476,213 @@ impl Photosynthesis {
     }
 }
 
+/// Static shape of the arena, relative to its transform
+#[derive(Component, Clone, Debug)]
+pub enum Terrain {
+    Circle(f32),
+    /// Width and height
+    Rectangle(Vec2),
+    Polygon(Vec<Vec2>),
+}
+
+/// Plant rooted in place
+#[derive(Component, Clone, Copy, Debug, PartialEq)]
+pub enum Rooted {
+    /// Static collider
+    Tree,
+    /// Sensor slowing the organisms inside by `density` per second and hiding them from
+    /// vision rays cast from outside
+    Bush { density: f32 },
+    /// Sensor without collisions
+    Grass,
+}
+
+/// Propagation of a plant through drifting seeds
+#[derive(Component, Clone, Copy, Debug)]
+pub struct Dispersal {
+    /// Seconds a seed drifts before germinating
+    pub drift_time: f32,
+    /// Largest initial speed of the seeds
+    pub speed: f32,
+    /// Seeds fail to germinate when this many plants grow within `crowding_radius`
+    pub crowding: u32,
+    pub crowding_radius: f32,
+    /// Seeds cling to the animals touching them
+    pub carried: bool,
+}
+
+/// Mating type of a sexually reproducing organism, mates must have different types
+#[derive(Component, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MatingType(pub u8);
+
+/// Heritable traits, from which the speed, size, vision, steering and colour of an
+/// organism are derived
+#[derive(Component, Clone, Debug, Serialize, Deserialize)]
+pub struct Genome {
+    pub speed: f32,
+    pub size: f32,
+    pub detection_range: f32,
+    /// Vision field of view in radians
+    pub field_of_view: f32,
+    /// Attraction toward prey
+    pub boldness: f32,
+    /// Repulsion from predators
+    pub fearfulness: f32,
+    /// Attraction toward conspecifics
+    pub sociability: f32,
+    /// Share of the maximum energy above which the organism reproduces
+    pub reproduction_threshold: f32,
+    pub color: [f32; 3],
+}
+impl Genome {
+    pub fn value(&self, gene: Gene) -> f32 {
+        match gene {
+            Gene::Speed => self.speed,
+            Gene::Size => self.size,
+            Gene::DetectionRange => self.detection_range,
+            Gene::FieldOfView => self.field_of_view,
+            Gene::Boldness => self.boldness,
+            Gene::Fearfulness => self.fearfulness,
+            Gene::Sociability => self.sociability,
+            Gene::ReproductionThreshold => self.reproduction_threshold,
+            Gene::Red => self.color[0],
+            Gene::Green => self.color[1],
+            Gene::Blue => self.color[2],
+        }
+    }
+
+    fn value_mut(&mut self, gene: Gene) -> &mut f32 {
+        match gene {
+            Gene::Speed => &mut self.speed,
+            Gene::Size => &mut self.size,
+            Gene::DetectionRange => &mut self.detection_range,
+            Gene::FieldOfView => &mut self.field_of_view,
+            Gene::Boldness => &mut self.boldness,
+            Gene::Fearfulness => &mut self.fearfulness,
+            Gene::Sociability => &mut self.sociability,
+            Gene::ReproductionThreshold => &mut self.reproduction_threshold,
+            Gene::Red => &mut self.color[0],
+            Gene::Green => &mut self.color[1],
+            Gene::Blue => &mut self.color[2],
+        }
+    }
+
+    /// Whether the organism has enough energy to reproduce
+    pub fn ready_to_reproduce(&self, energy: &Energy) -> bool {
+        energy.value() >= self.reproduction_threshold * energy.max
+    }
+
+    /// Genome taking each gene from either parent
+    pub fn recombined(&self, other: &Genome, rng: &mut impl Rng) -> Self {
+        let mut genome = self.clone();
+        for gene in Gene::ALL {
+            if rng.random_bool(0.5) {
+                *genome.value_mut(gene) = other.value(gene);
+            }
+        }
+        genome
+    }
+
+    /// Copy of the genome in which each gene mutates with the species mutation rate
+    pub fn mutated(&self, mutation: &Mutation, rng: &mut impl Rng) -> Self {
+        let mut genome = self.clone();
+        for gene in Gene::ALL {
+            let rate = mutation.rates[gene as usize];
+            if rate <= 0.0 || rng.random::<f32>() >= rate {
+                continue;
+            }
+            let value = genome.value_mut(gene);
+            let change = mutation.strength * value.abs().max(1.0) * rng.random_range(-1.0..=1.0);
+            let (min, max) = mutation.bounds[gene as usize];
+            *value = (*value + change).clamp(min, max);
+        }
+        genome
+    }
+}
+
+/// Heritable trait of a genome
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Gene {
+    Speed,
+    Size,
+    DetectionRange,
+    FieldOfView,
+    Boldness,
+    Fearfulness,
+    Sociability,
+    ReproductionThreshold,
+    Red,
+    Green,
+    Blue,
+}
+impl Gene {
+    pub const ALL: [Gene; 11] = [
+        Gene::Speed,
+        Gene::Size,
+        Gene::DetectionRange,
+        Gene::FieldOfView,
+        Gene::Boldness,
+        Gene::Fearfulness,
+        Gene::Sociability,
+        Gene::ReproductionThreshold,
+        Gene::Red,
+        Gene::Green,
+        Gene::Blue,
+    ];
+
+    /// Lore key of the gene
+    pub fn key(&self) -> &'static str {
+        match self {
+            Gene::Speed => "speed",
+            Gene::Size => "size",
+            Gene::DetectionRange => "detection_range",
+            Gene::FieldOfView => "field_of_view",
+            Gene::Boldness => "boldness",
+            Gene::Fearfulness => "fearfulness",
+            Gene::Sociability => "sociability",
+            Gene::ReproductionThreshold => "reproduction_threshold",
+            Gene::Red => "red",
+            Gene::Green => "green",
+            Gene::Blue => "blue",
+        }
+    }
+
+    pub fn from_key(key: &str) -> Option<Gene> {
+        Gene::ALL.into_iter().find(|gene| gene.key() == key)
+    }
+
+    /// Bounds keeping a gene meaningful, narrowed by the lore bounds
+    pub fn limits(&self) -> (f32, f32) {
+        match self {
+            Gene::Speed => (0.0, f32::MAX),
+            Gene::Size | Gene::DetectionRange => (1.0, f32::MAX),
+            Gene::FieldOfView => (0.01, std::f32::consts::TAU),
+            Gene::Boldness | Gene::Fearfulness | Gene::Sociability => (f32::MIN, f32::MAX),
+            Gene::ReproductionThreshold => (0.01, 1.0),
+            Gene::Red | Gene::Green | Gene::Blue => (0.0, 1.0),
+        }
+    }
+}
+
+/// Mutation rates and bounds of the genes of a species
+#[derive(Clone, Debug)]
+pub struct Mutation {
+    /// Probability that each gene mutates when a child is born
+    pub rates: [f32; Gene::ALL.len()],
+    /// Largest change of a mutated gene, relative to its value or to 1 for smaller values
+    pub strength: f32,
+    pub bounds: [(f32, f32); Gene::ALL.len()],
+}
+impl Default for Mutation {
+    fn default() -> Self {
+        Self {
+            rates: [0.0; Gene::ALL.len()],
+            strength: 0.0,
+            bounds: Gene::ALL.map(|gene| gene.limits()),
+        }
+    }
+}
+
 /// HUD
 #[derive(Component)]
 pub struct HudTitle;
@@ -264,3 +736,76 @@ pub struct RaycastVisualization;
 
 #[derive(Component)]
 pub struct HitPointVisualization;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+
+    fn genome(value: f32) -> Genome {
+        Genome {
+            speed: value * 40.0,
+            size: value * 4.0,
+            detection_range: value * 50.0,
+            field_of_view: value * 2.0,
+            boldness: value,
+            fearfulness: -value,
+            sociability: value,
+            reproduction_threshold: value * 0.8,
+            color: [value * 0.5; 3],
+        }
+    }
+
+    #[test]
+    fn genomes_without_mutation_rates_stay_unchanged() {
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        let parent = genome(1.0);
+        let mutation = Mutation {
+            strength: 0.5,
+            ..Mutation::default()
+        };
+        let child = parent.mutated(&mutation, &mut rng);
+        for gene in Gene::ALL {
+            assert_eq!(child.value(gene), parent.value(gene));
+        }
+    }
+
+    #[test]
+    fn mutated_genes_stay_within_their_bounds() {
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        let mut mutation = Mutation {
+            rates: [1.0; Gene::ALL.len()],
+            strength: 2.0,
+            ..Mutation::default()
+        };
+        mutation.bounds[Gene::Speed as usize] = (30.0, 50.0);
+        let parent = genome(1.0);
+        let mut changed = false;
+        for _ in 0..100 {
+            let child = parent.mutated(&mutation, &mut rng);
+            for gene in Gene::ALL {
+                let (min, max) = mutation.bounds[gene as usize];
+                assert!((min..=max).contains(&child.value(gene)));
+                changed |= child.value(gene) != parent.value(gene);
+            }
+        }
+        assert!(changed);
+    }
+
+    #[test]
+    fn recombined_genes_come_from_either_parent() {
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        let (mother, father) = (genome(1.0), genome(0.5));
+        let mut from_father = 0;
+        for _ in 0..20 {
+            let child = mother.recombined(&father, &mut rng);
+            for gene in Gene::ALL {
+                let value = child.value(gene);
+                assert!(value == mother.value(gene) || value == father.value(gene));
+                from_father += (value == father.value(gene)) as usize;
+            }
+        }
+        assert!(from_father > 0 && from_father < 20 * Gene::ALL.len());
+    }
+}
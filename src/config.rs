@@ -1,18 +1,25 @@
-use crate::components::HudBatch;
+use crate::components::{
+    BehaviourParams, Brain, Dispersal, Gene, Genome, HudBatch, Mutation, Network, Rooted,
+    Schooling, SpeciesRegistry,
+};
+use crate::resources::NetworkArchive;
+use bevy::math::Rect;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
 
 /// Video
 pub const WINDOW_WIDTH: f32 = 1080.0;
 pub const WINDOW_HEIGHT: f32 = 1920.0;
 pub const FRAMERATE: f32 = 30.0;
 pub const MAX_DURATION: f32 = 61.0;
-pub const MAX_FRAMES_TO_CAPTURE: u32 = MAX_DURATION as u32 * FRAMERATE as u32;
-pub const FIXED_TIME_STEP: f32 = 1.0 / FRAMERATE;
+pub const OUTPUTS_DIR: &str = "./outputs";
 
 ///Z-axis
 pub const Z_WATER: f32 = 0.0;
+pub const Z_BASIN: f32 = 0.5;
 pub const Z_HUD: f32 = 1.0;
+pub const Z_CARCASSES: f32 = 1.5;
 pub const Z_ENTITIES: f32 = 2.0;
 
 /// HUD
@@ -27,10 +34,20 @@ pub const TEXT_FONT_SIZE: f32 = 26.0;
 pub const MOVEMENT_ENERGY_COST_FACTOR: f32 = 1.0E-4;
 pub const ENERGY_TRANSFER_RATE: f32 = 1.0 / 3.0;
 pub const IDLE_ENERGY_LOSS: f32 = 3.0;
+// Carcasses
+pub const CARCASS_ENERGY_RATIO: f32 = 0.5;
+pub const CARCASS_DECAY_RATE: f32 = 0.05;
+pub const CARCASS_DRAG: f32 = 4.0;
+// Nutrients
+pub const NUTRIENT_CELL_SIZE: f32 = 40.0;
+pub const NUTRIENT_INITIAL: f32 = 100.0;
+pub const NUTRIENT_DIFFUSION: f32 = 0.5;
+pub const NUTRIENT_RECYCLING: f32 = 1.0;
 // Vision
 pub const WEIGHT_PREY: f32 = 6.0;
 pub const WEIGHT_PREDATOR: f32 = -18.0;
 pub const WEIGHT_NEUTRAL: f32 = 0.0;
+pub const WEIGHT_MATE: f32 = 12.0;
 // Movements
 pub const TURN_RESPONSIVENESS: f32 = 20.0;
 pub const ACCELERATION_FORCE: f32 = 800.0;
@@ -38,26 +55,259 @@ pub const LINEAR_DAMPING: f32 = 0.5; // Simulates water drag
 pub const ANGULAR_DAMPING: f32 = 1.0;
 pub const FORWARD_ALIGNMENT_THRESHOLD: f32 = 0.2;
 
+// Seeds
+pub const SEED_DRIFT_TIME: f32 = 3.0;
+pub const SEED_SPEED: f32 = 40.0;
+pub const SEED_DRAG: f32 = 1.0;
+pub const SEED_CROWDING: u32 = 4;
+
+// Behaviour
+pub const BEHAVIOUR_HUNGER: f32 = 0.6;
+pub const BEHAVIOUR_SATIETY: f32 = 0.9;
+pub const BEHAVIOUR_REST_TIME: f32 = 3.0;
+pub const BEHAVIOUR_CALM_TIME: f32 = 1.0;
+pub const BEHAVIOUR_WANDER_INTERVAL: f32 = 2.0;
+pub const BEHAVIOUR_WANDER_TURN: f32 = 90.0;
+
+// Neural networks
+pub const NETWORK_SECTORS: usize = 8;
+pub const NETWORK_HIDDEN: usize = 8;
+pub const NETWORK_MUTATION_RATE: f32 = 0.1;
+pub const NETWORK_MUTATION_STRENGTH: f32 = 0.5;
+pub const NETWORK_EXPORTS: usize = 16;
+
+// Hunting and fleeing strategies
+pub const AMBUSH_PROXIMITY: f32 = 0.6;
+pub const ZIGZAG_PERIOD: f32 = 0.6;
+pub const ZIGZAG_ANGLE: f32 = 50.0;
+pub const BURST_PROXIMITY: f32 = 0.5;
+pub const BURST_ACCELERATION: f32 = 3.0;
+pub const BURST_ENERGY_COST: f32 = 20.0;
+
+// Schooling
+pub const SCHOOLING_SEPARATION: f32 = 2.0;
+pub const SCHOOLING_ALIGNMENT: f32 = 1.0;
+pub const SCHOOLING_COHESION: f32 = 1.0;
+pub const SCHOOLING_SPACING: f32 = 3.0;
+
+// Arena
+pub const MAX_SPAWN_ATTEMPTS: u32 = 100;
+pub const REED_STEM_RADIUS: f32 = 3.0;
+
 /// DEBUG
 pub const DEBUG_FONT_SIZE: f32 = 20.0;
 pub const DEBUG_POS_PADDING: f32 = 2.0;
 pub const FRAMES_PER_UPDATE: u32 = 180;
 
+/// Positions of the walls of the video frame
+pub struct Layout {
+    pub half_width: f32,
+    pub half_height: f32,
+    pub middle_wall_y: f32,
+}
+
+impl Layout {
+    pub fn new() -> Self {
+        let half_width = WINDOW_WIDTH / 2.0;
+        let half_height = WINDOW_HEIGHT / 2.0;
+        let middle_wall_y = -half_height + WALLS_THICKNESS / 2.0 + (WINDOW_HEIGHT - WINDOW_WIDTH);
+        Self {
+            half_width,
+            half_height,
+            middle_wall_y,
+        }
+    }
+
+    /// Water area enclosed by the walls
+    pub fn water_area(&self) -> Rect {
+        Rect::new(
+            -self.half_width + WALLS_THICKNESS,
+            self.middle_wall_y + WALLS_THICKNESS / 2.0,
+            self.half_width - WALLS_THICKNESS,
+            self.half_height - WALLS_THICKNESS,
+        )
+    }
+}
+
+/// Lore compiled into the binary, used when no lore path is configured
+const EMBEDDED_LORE: &str = include_str!("lore.toml");
+
 /// TOML Configuration Structures
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct LoreConfig {
     pub biomes: HashMap<String, BiomeData>,
 }
 
+impl LoreConfig {
+    /// Load lore from a single TOML file, or merge every TOML file of a directory
+    pub fn load(path: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        if !path.is_dir() {
+            return Self::from_file(path);
+        }
+
+        // Collect lore files, sorted so merge errors are reported consistently
+        let mut files = Vec::new();
+        for entry in std::fs::read_dir(path)
+            .map_err(|e| format!("Failed to read lore directory {}: {}", path.display(), e))?
+        {
+            let file = entry?.path();
+            if file.is_file() && file.extension().is_some_and(|ext| ext == "toml") {
+                files.push(file);
+            }
+        }
+        files.sort();
+        if files.is_empty() {
+            return Err(format!("No lore TOML file found in {}", path.display()).into());
+        }
+
+        // Merge biomes, rejecting biome or species keys defined in several files
+        let mut lore = LoreConfig {
+            biomes: HashMap::new(),
+        };
+        let mut biome_sources: HashMap<String, PathBuf> = HashMap::new();
+        let mut species_sources: HashMap<String, PathBuf> = HashMap::new();
+        for file in files {
+            let part = Self::from_file(&file)?;
+            for (biome_key, biome) in part.biomes {
+                if let Some(source) = biome_sources.get(&biome_key) {
+                    return Err(format!(
+                        "Biome '{}' is defined in both {} and {}",
+                        biome_key,
+                        source.display(),
+                        file.display()
+                    )
+                    .into());
+                }
+                for species_key in biome.species.keys() {
+                    if let Some(source) = species_sources.get(species_key) {
+                        return Err(format!(
+                            "Species '{}' is defined in both {} and {}",
+                            species_key,
+                            source.display(),
+                            file.display()
+                        )
+                        .into());
+                    }
+                    species_sources.insert(species_key.clone(), file.clone());
+                }
+                biome_sources.insert(biome_key.clone(), file.clone());
+                lore.biomes.insert(biome_key, biome);
+            }
+        }
+
+        Ok(lore)
+    }
+
+    /// Parse the lore embedded in the binary
+    pub fn embedded() -> Result<Self, Box<dyn std::error::Error>> {
+        toml::from_str(EMBEDDED_LORE)
+            .map_err(|e| format!("Failed to parse embedded lore config: {}", e).into())
+    }
+
+    fn from_file(path: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read lore file {}: {}", path.display(), e))?;
+        let mut lore: Self = toml::from_str(&content)
+            .map_err(|e| format!("Failed to parse lore file {}: {}", path.display(), e))?;
+        let lore_dir = path.parent().unwrap_or(Path::new(""));
+        for biome in lore.biomes.values_mut() {
+            biome.arena = biome.arena.relative_to(lore_dir);
+        }
+        Ok(lore)
+    }
+}
+
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct BiomeData {
     pub name: String,
     #[serde(rename = "type")]
     pub biome_type: String,
     pub environment: Environment,
+    #[serde(default)]
+    pub arena: ArenaData,
     pub species: HashMap<String, SpeciesData>,
 }
 
+/// Geography of a biome, in pixels from the centre of the water area
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+pub struct ArenaData {
+    /// Outline of the basin, the whole water area when unset
+    #[serde(default)]
+    pub basin: Option<Vec<[f32; 2]>>,
+    /// PNG stretched over the water area, dark pixels being land. Relative paths start
+    /// from the directory of the file defining the arena
+    #[serde(default)]
+    pub mask: Option<PathBuf>,
+    #[serde(default)]
+    pub obstacles: Vec<ObstacleData>,
+}
+
+/// Static obstacle, rendered in the frame colour of the biome
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum ObstacleData {
+    Rock {
+        position: [f32; 2],
+        radius: f32,
+    },
+    /// Elliptic island
+    Island {
+        position: [f32; 2],
+        radii: [f32; 2],
+    },
+    /// Rectangle of reed stems, small organisms slipping between them
+    Reeds {
+        position: [f32; 2],
+        size: [f32; 2],
+        spacing: f32,
+        #[serde(default = "default_stem_radius")]
+        stem_radius: f32,
+    },
+    Polygon {
+        points: Vec<[f32; 2]>,
+    },
+}
+
+fn default_stem_radius() -> f32 {
+    REED_STEM_RADIUS
+}
+
+impl ArenaData {
+    /// Copy of the arena whose mask path starts from `dir` when relative
+    fn relative_to(&self, dir: &Path) -> Self {
+        let mut arena = self.clone();
+        if let Some(mask) = arena.mask.as_mut() {
+            *mask = dir.join(&*mask);
+        }
+        arena
+    }
+
+    fn validate(&self) -> Result<(), Box<dyn std::error::Error>> {
+        if let Some(basin) = &self.basin
+            && basin.len() < 3
+        {
+            return Err("Arena basin needs at least 3 points".into());
+        }
+        for obstacle in &self.obstacles {
+            let valid = match obstacle {
+                ObstacleData::Rock { radius, .. } => *radius > 0.0,
+                ObstacleData::Island { radii, .. } => radii[0] > 0.0 && radii[1] > 0.0,
+                ObstacleData::Reeds {
+                    size,
+                    spacing,
+                    stem_radius,
+                    ..
+                } => size[0] >= 0.0 && size[1] >= 0.0 && *spacing > 0.0 && *stem_radius > 0.0,
+                ObstacleData::Polygon { points } => points.len() >= 3,
+            };
+            if !valid {
+                return Err(format!("Invalid arena obstacle: {:?}", obstacle).into());
+            }
+        }
+        Ok(())
+    }
+}
+
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct Environment {
     pub water_color: [f32; 3],
@@ -74,12 +324,327 @@ pub struct SpeciesData {
     pub description: String,
     #[serde(default)]
     pub eats: Vec<String>,
+    /// Species keys whose carcasses are eaten
+    #[serde(default)]
+    pub scavenges: Vec<String>,
+    // Optional overrides of the size-derived defaults
+    #[serde(default)]
+    pub max_speed: Option<f32>,
+    #[serde(default)]
+    pub max_energy: Option<f32>,
+    #[serde(default)]
+    pub initial_energy_ratio: Option<f32>,
+    #[serde(default)]
+    pub detection_range: Option<f32>,
+    #[serde(default)]
+    pub nb_rays: Option<u32>,
+    /// Vision field of view in degrees
+    #[serde(default)]
+    pub field_of_view: Option<f32>,
+    #[serde(default)]
+    pub photosynthesis_rate: Option<f32>,
+    #[serde(default)]
+    pub turn_responsiveness: Option<f32>,
+    #[serde(default)]
+    pub acceleration: Option<f32>,
+    /// Mass density of the collider, or for bushes the drag slowing organisms inside
+    #[serde(default)]
+    pub density: Option<f32>,
+    /// Rooting of flora, which floats freely when unset
+    #[serde(default)]
+    pub growth_form: Option<GrowthForm>,
+    // Steering weights applied to what the vision rays hit
+    /// Attraction toward prey
+    #[serde(default)]
+    pub boldness: Option<f32>,
+    /// Repulsion from predators
+    #[serde(default)]
+    pub fearfulness: Option<f32>,
+    /// Attraction toward conspecifics, negative values repel
+    #[serde(default)]
+    pub sociability: Option<f32>,
+    /// Signed steering weight per species key, overriding the relationship weights
+    #[serde(default)]
+    pub weights: HashMap<String, f32>,
+    /// Attraction toward compatible mates, for sexually reproducing species
+    #[serde(default)]
+    pub libido: Option<f32>,
+    /// Share of the maximum energy above which an organism reproduces
+    #[serde(default)]
+    pub reproduction_threshold: Option<f32>,
+    /// Number of mating types, the species reproduces sexually when set. Mates must
+    /// meet and have different types
+    #[serde(default)]
+    pub mating_types: Option<u8>,
+    /// Decision model of fauna, steering from the weighted rays when unset
+    #[serde(default)]
+    pub brain: Option<DecisionModel>,
+    /// Transitions of the behaviour state machine, for the `behaviour` brain
+    #[serde(default)]
+    pub behaviour: Option<BehaviourData>,
+    /// Shape and mutations of the network, for the `neural` brain
+    #[serde(default)]
+    pub network: Option<NetworkData>,
+    /// How prey is chased, straight at it by default
+    #[serde(default)]
+    pub hunting: Option<HuntingStrategy>,
+    /// How predators are escaped, straight away from them by default
+    #[serde(default)]
+    pub fleeing: Option<FleeingStrategy>,
+    /// Boids steering among sighted conspecifics, on top of the sociability
+    #[serde(default)]
+    pub schooling: Option<SchoolingData>,
+    #[serde(default)]
+    pub mutation: Option<MutationData>,
+    /// Flora reproduce through drifting seeds when set, instead of budding in place
+    #[serde(default)]
+    pub seeds: Option<SeedData>,
+}
+
+/// Dispersal of the seeds of a flora species
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(default)]
+pub struct SeedData {
+    /// Seconds a seed drifts before germinating
+    pub drift_time: f32,
+    /// Largest initial speed of the seeds, in pixels per second
+    pub speed: f32,
+    /// Seeds fail to germinate when this many plants grow within `crowding_radius`
+    pub crowding: u32,
+    /// Radius in pixels, four times the plant size when unset
+    pub crowding_radius: Option<f32>,
+    /// Seeds cling to the animals touching them until their drift time is over
+    pub carried: bool,
+}
+impl Default for SeedData {
+    fn default() -> Self {
+        Self {
+            drift_time: SEED_DRIFT_TIME,
+            speed: SEED_SPEED,
+            crowding: SEED_CROWDING,
+            crowding_radius: None,
+            carried: false,
+        }
+    }
+}
+
+/// Transition parameters of the behaviour state machine
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(default)]
+pub struct BehaviourData {
+    /// Share of the maximum energy below which sighted food is pursued
+    pub hunger: f32,
+    /// Share of the maximum energy at which foraging stops for a rest
+    pub satiety: f32,
+    /// Seconds resting after a meal
+    pub rest_time: f32,
+    /// Seconds fleeing after the last predator went out of sight
+    pub calm_time: f32,
+    /// Seconds between changes of the wandering heading
+    pub wander_interval: f32,
+    /// Largest change of the wandering heading, in degrees
+    pub wander_turn: f32,
+}
+impl Default for BehaviourData {
+    fn default() -> Self {
+        Self {
+            hunger: BEHAVIOUR_HUNGER,
+            satiety: BEHAVIOUR_SATIETY,
+            rest_time: BEHAVIOUR_REST_TIME,
+            calm_time: BEHAVIOUR_CALM_TIME,
+            wander_interval: BEHAVIOUR_WANDER_INTERVAL,
+            wander_turn: BEHAVIOUR_WANDER_TURN,
+        }
+    }
+}
+
+/// Separation, alignment and cohesion weights of a schooling species
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(default)]
+pub struct SchoolingData {
+    /// Repulsion from conspecifics closer than the spacing
+    pub separation: f32,
+    /// Attraction toward the mean heading of sighted conspecifics
+    pub alignment: f32,
+    /// Attraction toward the centre of sighted conspecifics
+    pub cohesion: f32,
+    /// Distance kept from conspecifics, in body radii
+    pub spacing: f32,
+}
+impl Default for SchoolingData {
+    fn default() -> Self {
+        Self {
+            separation: SCHOOLING_SEPARATION,
+            alignment: SCHOOLING_ALIGNMENT,
+            cohesion: SCHOOLING_COHESION,
+            spacing: SCHOOLING_SPACING,
+        }
+    }
+}
+
+/// Neural network brain of a fauna species
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(default)]
+pub struct NetworkData {
+    /// Units of the hidden layer
+    pub hidden: usize,
+    /// Probability that each weight mutates when a child is born
+    pub mutation_rate: f32,
+    /// Largest change of a mutated weight
+    pub mutation_strength: f32,
+}
+impl Default for NetworkData {
+    fn default() -> Self {
+        Self {
+            hidden: NETWORK_HIDDEN,
+            mutation_rate: NETWORK_MUTATION_RATE,
+            mutation_strength: NETWORK_MUTATION_STRENGTH,
+        }
+    }
+}
+
+/// Decision models of fauna
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum DecisionModel {
+    /// Sum of the ray directions weighted by the steering weights
+    Weighted,
+    /// Movement set by an application embedding the simulation, not by the command line
+    External,
+    /// Wander, forage, flee, rest and seek mates depending on energy and sightings
+    Behaviour,
+    /// Feed-forward network inherited with mutations
+    Neural,
+}
+
+/// Ways of chasing prey
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum HuntingStrategy {
+    /// Toward where the prey is
+    #[default]
+    Pursuit,
+    /// Toward where the prey will be, from its velocity
+    Intercept,
+    /// Wait until the prey comes close before striking
+    Ambush,
+}
+
+/// Ways of escaping predators
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum FleeingStrategy {
+    /// Straight away from the predator
+    #[default]
+    Away,
+    /// Away from the predator, swerving from side to side
+    Zigzag,
+    /// Toward the closest obstacle or neutral organism on the far side of the predator
+    Cover,
+    /// Away from the predator, accelerating at the cost of energy when it is close
+    Burst,
+}
+
+/// Rooted plant forms
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum GrowthForm {
+    /// Static collider that organisms bump into
+    Tree,
+    /// Sensor slowing the organisms inside and hiding them from vision
+    Bush,
+    /// Sensor without collisions, still grazed on contact
+    Grass,
+}
+
+/// Mutations of the genome applied to children, genes are named `speed`, `size`,
+/// `detection_range`, `field_of_view` (degrees), `boldness`, `fearfulness`, `sociability`,
+/// `reproduction_threshold`, `red`, `green` and `blue`
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct MutationData {
+    /// Probability that each gene mutates when a child is born
+    #[serde(default)]
+    pub rate: f32,
+    /// Largest change of a mutated gene, relative to its value or to 1 for smaller values
+    #[serde(default = "default_mutation_strength")]
+    pub strength: f32,
+    /// Mutation probability per gene, overriding `rate`
+    #[serde(default)]
+    pub rates: HashMap<String, f32>,
+    /// Inclusive `[min, max]` bounds per gene
+    #[serde(default)]
+    pub bounds: HashMap<String, [f32; 2]>,
+}
+
+fn default_mutation_strength() -> f32 {
+    0.1
+}
+
+impl MutationData {
+    /// Rates and bounds indexed by gene
+    pub fn to_mutation(&self) -> Mutation {
+        let mut mutation = Mutation {
+            strength: self.strength,
+            ..Mutation::default()
+        };
+        for gene in Gene::ALL {
+            mutation.rates[gene as usize] =
+                self.rates.get(gene.key()).copied().unwrap_or(self.rate);
+            if let Some(bounds) = self.bounds.get(gene.key()) {
+                mutation.bounds[gene as usize] =
+                    Self::gene_bounds(gene, *bounds).expect("Mutation bounds not validated");
+            }
+        }
+        mutation
+    }
+
+    /// Configured bounds of a gene restricted to its limits, the field of view being
+    /// converted to radians. Bounds left empty by the restriction are an error
+    pub fn gene_bounds(gene: Gene, [min, max]: [f32; 2]) -> Result<(f32, f32), String> {
+        let (min, max) = if gene == Gene::FieldOfView {
+            (min.to_radians(), max.to_radians())
+        } else {
+            (min, max)
+        };
+        let (lower, upper) = gene.limits();
+        let (min, max) = (min.max(lower), max.min(upper));
+        if min > max || min.is_nan() || max.is_nan() {
+            return Err(format!("outside the limits [{}, {}]", lower, upper));
+        }
+        Ok((min, max))
+    }
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct SimulationConfig {
     pub simulation: SimulationMeta,
     pub biome: String,
+    #[serde(default)]
+    pub stop: StopConfig,
+    /// Lore file or directory of lore files, relative to this configuration file.
+    /// The embedded lore is used when absent
+    #[serde(default)]
+    pub lore: Option<PathBuf>,
+    /// Networks exported by a previous run, relative to this configuration file. The
+    /// founders of neural species take them in turn
+    #[serde(default)]
+    pub networks: Option<PathBuf>,
+    #[serde(default)]
+    pub energy: EnergyConfig,
+    #[serde(default)]
+    pub carcasses: CarcassConfig,
+    /// Nutrient field feeding photosynthesis, plants regenerate freely when absent
+    #[serde(default)]
+    pub nutrients: Option<NutrientConfig>,
+    /// Water current carrying the seeds, in pixels per second
+    #[serde(default)]
+    pub current: [f32; 2],
+    /// Geography replacing the arena of the biome
+    #[serde(default)]
+    pub arena: Option<ArenaData>,
+    /// Lore parameters overridden per species key, e.g. `[species.dravym] max_speed = 60.0`
+    #[serde(default)]
+    pub species: HashMap<String, toml::Table>,
     pub populations: HashMap<String, u32>,
 }
 
@@ -87,6 +652,116 @@ pub struct SimulationConfig {
 pub struct SimulationMeta {
     pub lab_name: String,
     pub run_id: String,
+    #[serde(default)]
+    pub seed: Option<u32>,
+    /// Deprecated alias of `stop.max_duration`
+    #[serde(default)]
+    pub duration: Option<f32>,
+    #[serde(default)]
+    pub framerate: Option<f32>,
+    #[serde(default)]
+    pub output_dir: Option<PathBuf>,
+}
+
+/// Energy exchanges shared by every species
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(default)]
+pub struct EnergyConfig {
+    /// Share of the prey energy gained by its predator
+    pub transfer_rate: f32,
+    /// Maximum energy lost per second by every entity
+    pub idle_loss: f32,
+}
+impl Default for EnergyConfig {
+    fn default() -> Self {
+        Self {
+            transfer_rate: ENERGY_TRANSFER_RATE,
+            idle_loss: IDLE_ENERGY_LOSS,
+        }
+    }
+}
+
+/// Remains left by organisms starving to death
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(default)]
+pub struct CarcassConfig {
+    /// Share of the maximum energy of the body kept by its carcass, 0 leaves no carcass
+    pub energy_ratio: f32,
+    /// Share of the maximum energy of the body lost per second
+    pub decay_rate: f32,
+    /// Linear damping of carcasses, high values sink them in place, low values let
+    /// them drift
+    pub drag: f32,
+}
+impl Default for CarcassConfig {
+    fn default() -> Self {
+        Self {
+            energy_ratio: CARCASS_ENERGY_RATIO,
+            decay_rate: CARCASS_DECAY_RATE,
+            drag: CARCASS_DRAG,
+        }
+    }
+}
+
+/// Grid of nutrients over the water area, recycled from idle energy losses and decaying
+/// carcasses, and drawn by photosynthesis from the local cell
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(default)]
+pub struct NutrientConfig {
+    /// Side of a cell in pixels
+    pub cell_size: f32,
+    /// Nutrients of each cell at the start of a run
+    pub initial: f32,
+    /// Share of the difference exchanged with each neighbouring cell per second
+    pub diffusion: f32,
+    /// Share of the idle energy losses deposited in the local cell
+    pub recycling: f32,
+}
+impl Default for NutrientConfig {
+    fn default() -> Self {
+        Self {
+            cell_size: NUTRIENT_CELL_SIZE,
+            initial: NUTRIENT_INITIAL,
+            diffusion: NUTRIENT_DIFFUSION,
+            recycling: NUTRIENT_RECYCLING,
+        }
+    }
+}
+
+/// Conditions ending a run, the first one met stops the simulation
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+pub struct StopConfig {
+    /// Simulated duration in seconds
+    #[serde(default)]
+    pub max_duration: Option<f32>,
+    #[serde(default)]
+    pub extinction: Option<Extinction>,
+    /// Population reached by a species, checked in species key order
+    #[serde(default)]
+    pub population_threshold: BTreeMap<String, u32>,
+    /// Total population under which the run stops
+    #[serde(default)]
+    pub min_total_population: Option<u32>,
+    #[serde(default)]
+    pub equilibrium: Option<EquilibriumConfig>,
+}
+
+/// Fauna extinction condition, only species with an initial population are considered
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Extinction {
+    /// One fauna species died out
+    Any,
+    /// Every fauna species died out
+    All,
+}
+
+/// Equilibrium is reached when every population stayed within `tolerance` (relative
+/// to its mean) during the last `window` seconds
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct EquilibriumConfig {
+    pub window: f32,
+    pub tolerance: f32,
 }
 
 /// Text wrapping utility for descriptions
@@ -130,31 +805,73 @@ pub fn wrap_text(text: &str, max_width: usize) -> String {
 pub struct RuntimeConfig {
     pub lore: LoreConfig,
     pub simulation: SimulationConfig,
+    pub species: SpeciesRegistry,
+    /// Mutations indexed by species
+    pub mutations: Vec<Mutation>,
+    /// Arena of the simulation, or else of the biome
+    pub arena: ArenaData,
+    /// Imported networks per species key
+    pub networks: HashMap<String, Vec<Network>>,
     pub title: String,
     pub batches: Vec<HudBatch>,
 }
 
 impl RuntimeConfig {
-    pub fn load() -> Result<Self, Box<dyn std::error::Error>> {
-        // Load embedded lore configuration
-        let lore_content = include_str!("lore.toml");
-
+    /// Load the simulation configuration and its lore. A lore path given here takes
+    /// precedence over the `lore` key of the simulation configuration
+    pub fn load(
+        config_path: &Path,
+        lore_path: Option<&Path>,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
         // Load simulation configuration from file at runtime
-        let simulation_content = std::fs::read_to_string("config/simulation.toml")
-            .map_err(|e| format!("Failed to read config/simulation.toml: {}. Make sure you run the program from the project root directory.", e))?;
-
-        let lore: LoreConfig = toml::from_str(lore_content)
-            .map_err(|e| format!("Failed to parse lore config: {}", e))?;
-        let simulation: SimulationConfig = toml::from_str(&simulation_content)
+        let simulation_content = std::fs::read_to_string(config_path).map_err(|e| {
+            format!(
+                "Failed to read simulation config {}: {}",
+                config_path.display(),
+                e
+            )
+        })?;
+        let mut simulation: SimulationConfig = toml::from_str(&simulation_content)
             .map_err(|e| format!("Failed to parse simulation config: {}", e))?;
+        if let Some(duration) = simulation.simulation.duration.take() {
+            eprintln!("`simulation.duration` is deprecated, use `stop.max_duration` instead");
+            simulation.stop.max_duration.get_or_insert(duration);
+        }
+
+        // Load lore from the given or configured path, falling back to the embedded lore
+        let config_dir = config_path.parent().unwrap_or(Path::new(""));
+        let mut lore = match (lore_path, &simulation.lore) {
+            (Some(path), _) => LoreConfig::load(path)?,
+            (None, Some(path)) => LoreConfig::load(&config_dir.join(path))?,
+            (None, None) => LoreConfig::embedded()?,
+        };
 
         // Validate biome exists
         let current_biome_key = &simulation.biome;
-        let current_biome = lore.biomes.get(current_biome_key).ok_or(format!(
-            "Biome '{}' not found in lore config. Available biomes: {:?}",
-            current_biome_key,
-            lore.biomes.keys().collect::<Vec<_>>()
-        ))?;
+        if !lore.biomes.contains_key(current_biome_key) {
+            return Err(format!(
+                "Biome '{}' not found in lore config. Available biomes: {:?}",
+                current_biome_key,
+                lore.biomes.keys().collect::<Vec<_>>()
+            )
+            .into());
+        }
+
+        // Apply the species overrides of the simulation config to the lore
+        if let Some(current_biome) = lore.biomes.get_mut(current_biome_key) {
+            for (species_key, overrides) in &simulation.species {
+                let species_data = current_biome.species.get_mut(species_key).ok_or(format!(
+                    "Overridden species '{}' not found in biome '{}'",
+                    species_key, current_biome_key
+                ))?;
+                let mut table = toml::Table::try_from(&*species_data)?;
+                table.extend(overrides.clone());
+                *species_data = table.try_into().map_err(|e| {
+                    format!("Invalid overrides of species '{}': {}", species_key, e)
+                })?;
+            }
+        }
+        let current_biome = &lore.biomes[current_biome_key];
 
         // Validate all population species exist in the biome
         for species_key in simulation.populations.keys() {
@@ -169,6 +886,226 @@ impl RuntimeConfig {
             }
         }
 
+        // Validate stop conditions
+        for species_key in simulation.stop.population_threshold.keys() {
+            if !current_biome.species.contains_key(species_key) {
+                return Err(format!(
+                    "Stop threshold species '{}' not found in biome '{}'",
+                    species_key, current_biome_key
+                )
+                .into());
+            }
+        }
+        if let Some(equilibrium) = &simulation.stop.equilibrium
+            && (equilibrium.window <= 0.0 || equilibrium.tolerance < 0.0)
+        {
+            return Err("Equilibrium window must be positive and tolerance non-negative".into());
+        }
+
+        // Validate the arena, whose mask the lore already resolved for biome arenas
+        let arena = match &simulation.arena {
+            Some(arena) => arena.relative_to(config_dir),
+            None => current_biome.arena.clone(),
+        };
+        arena.validate()?;
+
+        // Load the imported networks, which must fit the brains of their species
+        let mut networks: HashMap<String, Vec<Network>> = HashMap::new();
+        if let Some(path) = &simulation.networks {
+            for archived in NetworkArchive::load(&config_dir.join(path))?.networks {
+                let hidden = current_biome
+                    .species
+                    .get(&archived.species)
+                    .filter(|species_data| species_data.brain == Some(DecisionModel::Neural))
+                    .map(|species_data| species_data.network.clone().unwrap_or_default().hidden)
+                    .ok_or(format!(
+                        "Imported network of '{}', which has no neural brain in biome '{}'",
+                        archived.species, current_biome_key
+                    ))?;
+                if archived.network.hidden != hidden || !archived.network.is_valid() {
+                    return Err(format!(
+                        "Imported network of '{}' does not fit its {} hidden units",
+                        archived.species, hidden
+                    )
+                    .into());
+                }
+                networks
+                    .entry(archived.species)
+                    .or_default()
+                    .push(archived.network);
+            }
+        }
+
+        // Validate energy parameters
+        if simulation.energy.transfer_rate < 0.0 || simulation.energy.idle_loss < 0.0 {
+            return Err("Energy transfer rate and idle loss must be non-negative".into());
+        }
+        let carcasses = &simulation.carcasses;
+        if !(0.0..=1.0).contains(&carcasses.energy_ratio)
+            || carcasses.decay_rate < 0.0
+            || carcasses.drag < 0.0
+        {
+            return Err(
+                "Carcass energy ratio must be in [0, 1], decay rate and drag non-negative".into(),
+            );
+        }
+        if let Some(nutrients) = &simulation.nutrients
+            && (nutrients.cell_size <= 0.0
+                || nutrients.initial < 0.0
+                || nutrients.diffusion < 0.0
+                || nutrients.recycling < 0.0)
+        {
+            return Err(
+                "Nutrient cell size must be positive, initial, diffusion and recycling non-negative"
+                    .into(),
+            );
+        }
+
+        // Validate per-species parameters
+        for (species_key, species_data) in &current_biome.species {
+            let invalid = |field: &str, value: String| -> Box<dyn std::error::Error> {
+                format!(
+                    "Species '{}' has an invalid {}: {}",
+                    species_key, field, value
+                )
+                .into()
+            };
+            let positive = [
+                ("max_energy", species_data.max_energy),
+                ("detection_range", species_data.detection_range),
+                ("density", species_data.density),
+            ];
+            for (field, value) in positive {
+                if let Some(value) = value
+                    && value <= 0.0
+                {
+                    return Err(invalid(field, value.to_string()));
+                }
+            }
+            let non_negative = [
+                ("max_speed", species_data.max_speed),
+                ("photosynthesis_rate", species_data.photosynthesis_rate),
+                ("turn_responsiveness", species_data.turn_responsiveness),
+                ("acceleration", species_data.acceleration),
+            ];
+            for (field, value) in non_negative {
+                if let Some(value) = value
+                    && value < 0.0
+                {
+                    return Err(invalid(field, value.to_string()));
+                }
+            }
+            if let Some(ratio) = species_data.initial_energy_ratio
+                && !(ratio > 0.0 && ratio <= 1.0)
+            {
+                return Err(invalid("initial_energy_ratio", ratio.to_string()));
+            }
+            if let Some(field_of_view) = species_data.field_of_view
+                && !(field_of_view > 0.0 && field_of_view <= 360.0)
+            {
+                return Err(invalid("field_of_view", field_of_view.to_string()));
+            }
+            if species_data.nb_rays == Some(0) {
+                return Err(invalid("nb_rays", "0".to_string()));
+            }
+            if let Some(threshold) = species_data.reproduction_threshold
+                && !(threshold > 0.0 && threshold <= 1.0)
+            {
+                return Err(invalid("reproduction_threshold", threshold.to_string()));
+            }
+            if let Some(mating_types) = species_data.mating_types
+                && mating_types < 2
+            {
+                return Err(invalid("mating_types", mating_types.to_string()));
+            }
+            if let Some(brain) = species_data.brain
+                && species_data.species_type != "Fauna"
+            {
+                return Err(invalid("brain", format!("{:?} for flora", brain)));
+            }
+            if let Some(hunting) = species_data.hunting
+                && species_data.species_type != "Fauna"
+            {
+                return Err(invalid("hunting", format!("{:?} for flora", hunting)));
+            }
+            if let Some(fleeing) = species_data.fleeing
+                && species_data.species_type != "Fauna"
+            {
+                return Err(invalid("fleeing", format!("{:?} for flora", fleeing)));
+            }
+            if let Some(behaviour) = &species_data.behaviour
+                && !(0.0 <= behaviour.hunger
+                    && behaviour.hunger <= behaviour.satiety
+                    && behaviour.satiety <= 1.0
+                    && behaviour.rest_time >= 0.0
+                    && behaviour.calm_time >= 0.0
+                    && behaviour.wander_interval > 0.0
+                    && behaviour.wander_turn >= 0.0)
+            {
+                return Err(invalid("behaviour", format!("{:?}", behaviour)));
+            }
+            if let Some(schooling) = &species_data.schooling
+                && !(species_data.species_type == "Fauna"
+                    && schooling.separation >= 0.0
+                    && schooling.alignment >= 0.0
+                    && schooling.cohesion >= 0.0
+                    && schooling.spacing > 0.0)
+            {
+                return Err(invalid("schooling", format!("{:?}", schooling)));
+            }
+            if let Some(network) = &species_data.network
+                && !(network.hidden > 0
+                    && (0.0..=1.0).contains(&network.mutation_rate)
+                    && network.mutation_strength >= 0.0)
+            {
+                return Err(invalid("network", format!("{:?}", network)));
+            }
+            if let Some(growth_form) = species_data.growth_form
+                && species_data.species_type != "Flora"
+            {
+                return Err(invalid(
+                    "growth_form",
+                    format!("{:?} for fauna", growth_form),
+                ));
+            }
+            if let Some(mutation) = &species_data.mutation {
+                if mutation.strength < 0.0 {
+                    return Err(invalid("mutation strength", mutation.strength.to_string()));
+                }
+                let rates = std::iter::once(("rate", &mutation.rate)).chain(
+                    mutation
+                        .rates
+                        .iter()
+                        .map(|(gene, rate)| (gene.as_str(), rate)),
+                );
+                for (gene, rate) in rates {
+                    if !(0.0..=1.0).contains(rate) {
+                        return Err(invalid("mutation rate", format!("{} = {}", gene, rate)));
+                    }
+                }
+                for gene in mutation.rates.keys().chain(mutation.bounds.keys()) {
+                    if Gene::from_key(gene).is_none() {
+                        return Err(invalid("mutation gene", gene.clone()));
+                    }
+                }
+                for (key, bounds @ [min, max]) in &mutation.bounds {
+                    let gene = Gene::from_key(key).expect("Mutation gene validated above");
+                    if min > max {
+                        return Err(invalid(
+                            "mutation bounds",
+                            format!("{} = [{}, {}]", key, min, max),
+                        ));
+                    }
+                    if let Err(e) = MutationData::gene_bounds(gene, *bounds) {
+                        return Err(invalid(
+                            "mutation bounds",
+                            format!("{} = [{}, {}], {}", key, min, max, e),
+                        ));
+                    }
+                }
+            }
+        }
+
         // Validate eating relationships
         for (species_key, species_data) in &current_biome.species {
             for prey_name in &species_data.eats {
@@ -180,6 +1117,39 @@ impl RuntimeConfig {
                     .into());
                 }
             }
+            for carrion_name in &species_data.scavenges {
+                if !current_biome.species.contains_key(carrion_name) {
+                    return Err(format!(
+                        "Species '{}' scavenges '{}' but '{}' is not defined in biome '{}'",
+                        species_key, carrion_name, carrion_name, current_biome_key
+                    )
+                    .into());
+                }
+            }
+            for target_name in species_data.weights.keys() {
+                if !current_biome.species.contains_key(target_name) {
+                    return Err(format!(
+                        "Species '{}' has a steering weight for '{}' but '{}' is not defined in biome '{}'",
+                        species_key, target_name, target_name, current_biome_key
+                    )
+                    .into());
+                }
+            }
+        }
+
+        // Intern species of the current biome, sorted so ids are stable across runs
+        let mut species_entries: Vec<_> = current_biome.species.iter().collect();
+        species_entries.sort_by_key(|(species_key, _)| *species_key);
+        let mut species = SpeciesRegistry::default();
+        let mut mutations = Vec::new();
+        for (species_key, species_data) in &species_entries {
+            species.register(species_key);
+            mutations.push(
+                species_data
+                    .mutation
+                    .as_ref()
+                    .map_or_else(Mutation::default, MutationData::to_mutation),
+            );
         }
 
         // Generate title from biome info
@@ -188,9 +1158,9 @@ impl RuntimeConfig {
             current_biome.name, current_biome.biome_type
         );
 
-        // Generate HUD batches from species data
+        // Generate HUD batches from species data, in a stable order for reproducible videos
         let mut batches = Vec::new();
-        for species_data in current_biome.species.values() {
+        for (_, species_data) in species_entries {
             if species_data.species_type == "Fauna" {
                 let batch = HudBatch {
                     title: title.clone(),
@@ -225,6 +1195,10 @@ impl RuntimeConfig {
         Ok(Self {
             lore,
             simulation,
+            species,
+            mutations,
+            arena,
+            networks,
             title,
             batches,
         })
@@ -237,6 +1211,24 @@ impl RuntimeConfig {
     pub fn get_batches(&self) -> &[HudBatch] {
         &self.batches
     }
+
+    /// Reject species with an external brain, which only an application embedding the
+    /// simulation can drive through `ExternalIntents`
+    pub fn check_no_external_brain(&self, run: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let biome = &self.lore.biomes[&self.simulation.biome];
+        let mut keys: Vec<_> = biome.species.keys().collect();
+        keys.sort();
+        for key in keys {
+            if biome.species[key].brain == Some(DecisionModel::External) {
+                return Err(format!(
+                    "Species '{}' has an external brain, which nothing drives in {}",
+                    key, run
+                )
+                .into());
+            }
+        }
+        Ok(())
+    }
 }
 
 /// Entity spawning parameters derived from config
@@ -248,8 +1240,27 @@ pub struct EntitySpawnParams {
     pub initial_energy: f32,
     pub max_energy: f32,
     pub detection_range: f32,
+    pub nb_rays: u32,
+    /// Vision field of view in radians
+    pub field_of_view: f32,
+    pub turn_responsiveness: f32,
+    pub acceleration: f32,
+    pub density: f32,
+    pub prey_weight: f32,
+    pub predator_weight: f32,
+    pub conspecific_weight: f32,
+    pub mate_weight: f32,
+    pub hunting: HuntingStrategy,
+    pub fleeing: FleeingStrategy,
+    pub schooling: Option<Schooling>,
     pub is_active_mover: bool,
     pub photosynthesis_rate: Option<f32>,
+    pub rooted: Option<Rooted>,
+    pub dispersal: Option<Dispersal>,
+    pub reproduction_threshold: f32,
+    /// Number of mating types, `None` for asexual reproduction
+    pub mating_types: Option<u8>,
+    pub brain: Brain,
 }
 
 impl EntitySpawnParams {
@@ -257,20 +1268,222 @@ impl EntitySpawnParams {
         let is_plant = data.species_type.to_lowercase() == "flora";
         let size = data.size as f32;
 
-        // Scale parameters based on size and type
+        // Scale parameters based on size and type, unless the lore overrides them
         let base_speed = if is_plant { 0.0 } else { 20.0 + size * 2.0 };
         let base_energy = size * 50.0;
         let detection_range = 10.0 + size * 10.0;
+        let max_energy = data.max_energy.unwrap_or(base_energy);
 
         Self {
             size,
             color: data.color,
-            max_speed: base_speed,
-            initial_energy: base_energy * 0.7, // Start at 70% of max
-            max_energy: base_energy,
-            detection_range,
+            max_speed: data.max_speed.unwrap_or(base_speed),
+            initial_energy: max_energy * data.initial_energy_ratio.unwrap_or(0.7), // 70% of max unless overridden
+            max_energy,
+            detection_range: data.detection_range.unwrap_or(detection_range),
+            nb_rays: data.nb_rays.unwrap_or(360),
+            field_of_view: data.field_of_view.unwrap_or(360.0).to_radians(),
+            turn_responsiveness: data.turn_responsiveness.unwrap_or(TURN_RESPONSIVENESS),
+            acceleration: data.acceleration.unwrap_or(ACCELERATION_FORCE),
+            density: data.density.unwrap_or(1.0),
+            prey_weight: data.boldness.unwrap_or(WEIGHT_PREY),
+            predator_weight: data.fearfulness.map_or(WEIGHT_PREDATOR, |fear| -fear),
+            conspecific_weight: data.sociability.unwrap_or(WEIGHT_NEUTRAL),
+            mate_weight: data.libido.unwrap_or(WEIGHT_MATE),
+            hunting: data.hunting.unwrap_or_default(),
+            fleeing: data.fleeing.unwrap_or_default(),
+            schooling: data.schooling.as_ref().map(|schooling| Schooling {
+                separation: schooling.separation,
+                alignment: schooling.alignment,
+                cohesion: schooling.cohesion,
+                spacing: schooling.spacing * size,
+            }),
             is_active_mover: !is_plant,
-            photosynthesis_rate: if is_plant { Some(10.0) } else { None },
+            photosynthesis_rate: if is_plant {
+                Some(data.photosynthesis_rate.unwrap_or(10.0))
+            } else {
+                None
+            },
+            rooted: data.growth_form.map(|growth_form| match growth_form {
+                GrowthForm::Tree => Rooted::Tree,
+                GrowthForm::Bush => Rooted::Bush {
+                    density: data.density.unwrap_or(1.0),
+                },
+                GrowthForm::Grass => Rooted::Grass,
+            }),
+            dispersal: data.seeds.as_ref().map(|seeds| Dispersal {
+                drift_time: seeds.drift_time,
+                speed: seeds.speed,
+                crowding: seeds.crowding,
+                crowding_radius: seeds.crowding_radius.unwrap_or(size * 4.0),
+                carried: seeds.carried,
+            }),
+            reproduction_threshold: data.reproduction_threshold.unwrap_or(0.8),
+            mating_types: data.mating_types,
+            brain: match data.brain.unwrap_or(DecisionModel::Weighted) {
+                DecisionModel::Weighted => Brain::Weighted,
+                DecisionModel::External => Brain::External,
+                DecisionModel::Behaviour => {
+                    let behaviour = data.behaviour.clone().unwrap_or_default();
+                    Brain::behaviour(BehaviourParams {
+                        hunger: behaviour.hunger,
+                        satiety: behaviour.satiety,
+                        rest_time: behaviour.rest_time,
+                        calm_time: behaviour.calm_time,
+                        wander_interval: behaviour.wander_interval,
+                        wander_turn: behaviour.wander_turn.to_radians(),
+                    })
+                }
+                DecisionModel::Neural => {
+                    let network = data.network.clone().unwrap_or_default();
+                    Brain::Neural {
+                        network: Network::zeroed(network.hidden),
+                        mutation_rate: network.mutation_rate,
+                        mutation_strength: network.mutation_strength,
+                    }
+                }
+            },
+        }
+    }
+
+    /// Genome of a founder organism moving at `speed`
+    pub fn genome(&self, speed: f32) -> Genome {
+        Genome {
+            speed,
+            size: self.size,
+            detection_range: self.detection_range,
+            field_of_view: self.field_of_view,
+            boldness: self.prey_weight,
+            fearfulness: -self.predator_weight,
+            sociability: self.conspecific_weight,
+            reproduction_threshold: self.reproduction_threshold,
+            color: self.color,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn biome(key: &str, species: &[&str]) -> String {
+        let mut toml = format!(
+            "[biomes.{key}]\nname = \"{key}\"\ntype = \"Pond\"\n\
+             [biomes.{key}.environment]\nwater_color = [0.0, 0.0, 0.0]\nframe_color = [1.0, 1.0, 1.0]\n"
+        );
+        for species_key in species {
+            toml += &format!(
+                "[biomes.{key}.species.{species_key}]\nname = \"{species_key}\"\ntype = \"Fauna\"\n\
+                 size = 4\ncolor = [0.5, 0.5, 0.5]\ndescription = \"\"\n"
+            );
+        }
+        toml
+    }
+
+    /// Lore directory holding one file per biome, emptied first
+    fn lore_dir(name: &str, files: &[(&str, String)]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("teemlabs_lore_{}", name));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        for (file, content) in files {
+            std::fs::write(dir.join(file), content).unwrap();
+        }
+        dir
+    }
+
+    #[test]
+    fn lore_directory_merges_biomes() {
+        let dir = lore_dir(
+            "merge",
+            &[
+                ("a.toml", biome("pond", &["fish"])),
+                ("b.toml", biome("lake", &["snail"])),
+            ],
+        );
+        let lore = LoreConfig::load(&dir).unwrap();
+        assert_eq!(lore.biomes.len(), 2);
+    }
+
+    #[test]
+    fn lore_directory_rejects_duplicate_biomes() {
+        let dir = lore_dir(
+            "duplicate",
+            &[
+                ("a.toml", biome("pond", &["fish"])),
+                ("b.toml", biome("pond", &["snail"])),
+            ],
+        );
+        let error = LoreConfig::load(&dir).unwrap_err().to_string();
+        assert!(
+            error.contains("Biome 'pond' is defined in both"),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn lore_directory_rejects_duplicate_species() {
+        let dir = lore_dir(
+            "duplicate_species",
+            &[
+                ("a.toml", biome("pond", &["fish"])),
+                ("b.toml", biome("lake", &["fish"])),
+            ],
+        );
+        let error = LoreConfig::load(&dir).unwrap_err().to_string();
+        assert!(
+            error.contains("Species 'fish' is defined in both"),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn lore_directory_requires_toml_files() {
+        let dir = lore_dir("empty", &[("notes.txt", String::new())]);
+        assert!(LoreConfig::load(&dir).is_err());
+    }
+
+    #[test]
+    fn species_keep_the_case_of_their_lore_key() {
+        let dir = lore_dir(
+            "species_case",
+            &[("lore.toml", biome("pond", &["Fish", "snail"]))],
+        );
+        let config = dir.join("simulation.toml");
+        std::fs::write(
+            &config,
+            "biome = \"pond\"\nlore = \"lore.toml\"\n\
+             [simulation]\nlab_name = \"lab\"\nrun_id = \"0\"\n\
+             [populations]\nFish = 1\nsnail = 1\n",
+        )
+        .unwrap();
+        let runtime = RuntimeConfig::load(&config, None).unwrap();
+        let fish = runtime.species.get("Fish").unwrap();
+        assert_eq!(runtime.species.key(fish), "Fish");
+        assert!(runtime.species.get("fish").is_none());
+    }
+
+    #[test]
+    fn mutation_bounds_are_restricted_to_gene_limits() {
+        assert_eq!(
+            MutationData::gene_bounds(Gene::Size, [0.0, 10.0]),
+            Ok((1.0, 10.0))
+        );
+        let (min, max) = MutationData::gene_bounds(Gene::FieldOfView, [90.0, 720.0]).unwrap();
+        assert_eq!(min, 90f32.to_radians());
+        assert_eq!(max, std::f32::consts::TAU);
+    }
+
+    #[test]
+    fn mutation_bounds_outside_gene_limits_are_rejected() {
+        assert!(MutationData::gene_bounds(Gene::Red, [2.0, 3.0]).is_err());
+        assert!(MutationData::gene_bounds(Gene::FieldOfView, [400.0, 500.0]).is_err());
+        assert!(MutationData::gene_bounds(Gene::ReproductionThreshold, [-1.0, 0.0]).is_err());
+    }
+
+    #[test]
+    fn embedded_lore_is_valid() {
+        LoreConfig::embedded().unwrap();
+    }
+}
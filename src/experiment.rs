@@ -0,0 +1,425 @@
+use rand::prelude::*;
+use rand_chacha::ChaCha8Rng;
+use serde::Deserialize;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::batch::{self, BatchOptions, BatchRun, RunOutcome, RunStatus};
+use crate::cli::ExperimentArgs;
+use crate::config::RuntimeConfig;
+
+/// Experiment file: a base simulation configuration and the parameters swept over it
+#[derive(Deserialize)]
+struct ExperimentConfig {
+    experiment: ExperimentMeta,
+    /// Swept values per dotted key of the simulation configuration,
+    /// e.g. `"populations.vyrmosa"`, `"energy.transfer_rate"` or `"species.dravym.max_speed"`
+    #[serde(default)]
+    axes: BTreeMap<String, Axis>,
+}
+
+#[derive(Deserialize)]
+struct ExperimentMeta {
+    name: String,
+    /// Base simulation configuration, relative to the experiment file
+    base: PathBuf,
+    #[serde(default)]
+    sampling: Sampling,
+    /// Seeds every parameter point is run with, a random one when empty
+    #[serde(default)]
+    seeds: Vec<u32>,
+    /// Seed of the random sampling of parameter points
+    #[serde(default)]
+    sampling_seed: Option<u32>,
+}
+
+#[derive(Deserialize, Default, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+enum Sampling {
+    /// Every combination of the axes values
+    #[default]
+    Grid,
+    /// Given number of points, each axis value drawn uniformly
+    Random(u32),
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Axis {
+    Values(Vec<toml::Value>),
+    /// Integer bounds yield integer values. Grids need `steps` evenly spaced values
+    Range {
+        min: toml::Value,
+        max: toml::Value,
+        #[serde(default)]
+        steps: Option<u32>,
+    },
+}
+
+impl Axis {
+    /// Values of the axis on a grid
+    fn grid(&self, key: &str) -> Result<Vec<toml::Value>, Box<dyn std::error::Error>> {
+        let values = match self {
+            Axis::Values(values) => values.clone(),
+            Axis::Range { min, max, steps } => {
+                let steps = steps.ok_or(format!("Axis '{}' needs steps for a grid", key))?;
+                let (min_value, max_value) = bounds(key, min, max)?;
+                (0..steps)
+                    .map(|step| {
+                        let ratio = if steps > 1 {
+                            step as f64 / (steps - 1) as f64
+                        } else {
+                            0.0
+                        };
+                        let value = min_value + (max_value - min_value) * ratio;
+                        if min.is_integer() && max.is_integer() {
+                            toml::Value::Integer(value.round() as i64)
+                        } else {
+                            toml::Value::Float(value)
+                        }
+                    })
+                    .collect()
+            }
+        };
+        if values.is_empty() {
+            return Err(format!("Axis '{}' has no value", key).into());
+        }
+        Ok(values)
+    }
+
+    /// Value of the axis drawn uniformly
+    fn sample(
+        &self,
+        key: &str,
+        rng: &mut ChaCha8Rng,
+    ) -> Result<toml::Value, Box<dyn std::error::Error>> {
+        match self {
+            Axis::Values(values) => values
+                .choose(rng)
+                .cloned()
+                .ok_or(format!("Axis '{}' has no value", key).into()),
+            Axis::Range { min, max, .. } => {
+                let (min_value, max_value) = bounds(key, min, max)?;
+                Ok(match (min.as_integer(), max.as_integer()) {
+                    (Some(min), Some(max)) => toml::Value::Integer(rng.random_range(min..=max)),
+                    _ => toml::Value::Float(rng.random_range(min_value..=max_value)),
+                })
+            }
+        }
+    }
+}
+
+/// Numeric bounds of a range axis
+fn bounds(
+    key: &str,
+    min: &toml::Value,
+    max: &toml::Value,
+) -> Result<(f64, f64), Box<dyn std::error::Error>> {
+    let number = |value: &toml::Value| {
+        value
+            .as_float()
+            .or(value.as_integer().map(|value| value as f64))
+    };
+    match (number(min), number(max)) {
+        (Some(min), Some(max)) if min <= max => Ok((min, max)),
+        _ => Err(format!("Axis '{}' has invalid range bounds", key).into()),
+    }
+}
+
+/// A combination of axes values
+struct Point {
+    config: PathBuf,
+    values: Vec<toml::Value>,
+}
+
+/// Expand an experiment file into runs, execute them and collect their results
+pub fn run_experiment(args: ExperimentArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let content = fs::read_to_string(&args.experiment).map_err(|e| {
+        format!(
+            "Failed to read experiment {}: {}",
+            args.experiment.display(),
+            e
+        )
+    })?;
+    let experiment: ExperimentConfig =
+        toml::from_str(&content).map_err(|e| format!("Failed to parse experiment: {}", e))?;
+    let meta = &experiment.experiment;
+
+    // Load the base configuration, resolving its paths before copies are written elsewhere
+    let experiment_dir = args.experiment.parent().unwrap_or(Path::new(""));
+    let base_path = experiment_dir.join(&meta.base);
+    let mut base: toml::Table = toml::from_str(
+        &fs::read_to_string(&base_path)
+            .map_err(|e| format!("Failed to read base config {}: {}", base_path.display(), e))?,
+    )
+    .map_err(|e| format!("Failed to parse base config: {}", e))?;
+    let base_dir = base_path.parent().unwrap_or(Path::new(""));
+    for key in ["lore", "networks"] {
+        if let Some(path) = base.get(key).and_then(toml::Value::as_str) {
+            let path = std::path::absolute(base_dir.join(path))?;
+            base.insert(key.into(), path.to_string_lossy().into_owned().into());
+        }
+    }
+    if let Some(arena) = base.get_mut("arena").and_then(toml::Value::as_table_mut)
+        && let Some(mask) = arena.get("mask").and_then(toml::Value::as_str)
+    {
+        let mask = std::path::absolute(base_dir.join(mask))?;
+        arena.insert("mask".into(), mask.to_string_lossy().into_owned().into());
+    }
+
+    // Expand the axes into parameter points
+    let mut rng = ChaCha8Rng::seed_from_u64(meta.sampling_seed.unwrap_or_else(rand::random) as u64);
+    let mut points: Vec<Vec<toml::Value>> = vec![Vec::new()];
+    match meta.sampling {
+        Sampling::Grid => {
+            for (key, axis) in &experiment.axes {
+                let values = axis.grid(key)?;
+                points = points
+                    .into_iter()
+                    .flat_map(|point| {
+                        values.iter().map(move |value| {
+                            let mut point = point.clone();
+                            point.push(value.clone());
+                            point
+                        })
+                    })
+                    .collect();
+            }
+        }
+        Sampling::Random(nb_samples) => {
+            points = (0..nb_samples)
+                .map(|_| {
+                    experiment
+                        .axes
+                        .iter()
+                        .map(|(key, axis)| axis.sample(key, &mut rng))
+                        .collect()
+                })
+                .collect::<Result<_, _>>()?;
+        }
+    }
+    if points.is_empty() {
+        return Err("The experiment expands to no parameter point".into());
+    }
+    let seeds = if meta.seeds.is_empty() {
+        vec![rand::random()]
+    } else {
+        meta.seeds.clone()
+    };
+
+    // Write and validate the configuration of every point
+    let dir = batch::create_batch_dir(args.output.as_deref(), &meta.name)?;
+    fs::copy(&args.experiment, dir.join("experiment.toml"))?;
+    let configs_dir = dir.join("configs");
+    fs::create_dir_all(&configs_dir)?;
+    let mut expanded = Vec::new();
+    for (index, values) in points.into_iter().enumerate() {
+        let mut config = base.clone();
+        for (key, value) in experiment.axes.keys().zip(&values) {
+            set_value(&mut config, key, value.clone())?;
+        }
+        let config_path = configs_dir.join(format!("{:03}.toml", index));
+        fs::write(&config_path, toml::to_string(&config)?)?;
+        RuntimeConfig::load(&config_path, args.lore.as_deref())
+            .and_then(|runtime_config| runtime_config.check_no_external_brain("batch runs"))
+            .map_err(|e| format!("Invalid configuration of point {}: {}", index, e))?;
+        expanded.push(Point {
+            config: config_path,
+            values,
+        });
+    }
+
+    let mut runs = Vec::new();
+    for (index, point) in expanded.iter().enumerate() {
+        for seed in &seeds {
+            runs.push(BatchRun {
+                name: format!("{:03}_{}", index, seed),
+                config: point.config.clone(),
+                seed: *seed,
+            });
+        }
+    }
+    if args.dry_run {
+        println!(
+            "{} points and {} runs written to {}",
+            expanded.len(),
+            runs.len(),
+            dir.display()
+        );
+        return Ok(());
+    }
+
+    let options = BatchOptions {
+        jobs: args.jobs.unwrap_or_else(batch::default_jobs),
+        timeout: args.timeout.map(Duration::from_secs_f64),
+        lore: args.lore,
+        duration: args.duration,
+        fps: args.fps,
+        render: args.render,
+    };
+    let outcomes = batch::execute(runs, &dir, &options)?;
+    let results_path = dir.join("results.csv");
+    write_results(
+        &results_path,
+        &dir,
+        experiment.axes.keys(),
+        &expanded,
+        seeds.len(),
+        &outcomes,
+    )?;
+    println!("Results written to {}", results_path.display());
+
+    Ok(())
+}
+
+/// Set the value of a dotted key, creating missing tables
+fn set_value(
+    table: &mut toml::Table,
+    key: &str,
+    value: toml::Value,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let (parents, last) = match key.rsplit_once('.') {
+        Some((parents, last)) => (Some(parents), last),
+        None => (None, key),
+    };
+    let mut table = table;
+    for part in parents.into_iter().flat_map(|parents| parents.split('.')) {
+        table = table
+            .entry(part)
+            .or_insert_with(|| toml::Table::new().into())
+            .as_table_mut()
+            .ok_or(format!(
+                "Axis '{}' does not name a configuration table",
+                key
+            ))?;
+    }
+    table.insert(last.to_string(), value);
+    Ok(())
+}
+
+/// One row per run, keyed by the axes values, with the final populations
+fn write_results<'a>(
+    path: &Path,
+    dir: &Path,
+    axes: impl Iterator<Item = &'a String>,
+    points: &[Point],
+    nb_seeds: usize,
+    outcomes: &[RunOutcome],
+) -> Result<(), Box<dyn std::error::Error>> {
+    let reports: Vec<_> = outcomes
+        .iter()
+        .map(|outcome| batch::read_stop_report(&dir.join(&outcome.name)))
+        .collect();
+    let species: BTreeSet<&String> = reports
+        .iter()
+        .flatten()
+        .flat_map(|report| report.populations.keys())
+        .collect();
+
+    let mut writer = BufWriter::new(fs::File::create(path)?);
+    write!(writer, "point,seed")?;
+    for key in axes {
+        write!(writer, ",{}", key)?;
+    }
+    write!(writer, ",status,stop_reason,time")?;
+    for species_key in &species {
+        write!(writer, ",{}_population", species_key)?;
+    }
+    writeln!(writer)?;
+
+    for (index, (outcome, report)) in outcomes.iter().zip(&reports).enumerate() {
+        write!(writer, "{},{}", index / nb_seeds, outcome.seed)?;
+        for value in &points[index / nb_seeds].values {
+            match value.as_str() {
+                Some(text) => write!(writer, ",{}", csv_field(text))?,
+                None => write!(writer, ",{}", value)?,
+            }
+        }
+        let status = match outcome.status {
+            RunStatus::Succeeded => "succeeded",
+            RunStatus::Failed => "failed",
+            RunStatus::TimedOut => "timed_out",
+        };
+        write!(writer, ",{}", status)?;
+        match report {
+            Some(report) => {
+                write!(writer, ",{},{}", csv_field(&report.reason), report.time)?;
+                for species_key in &species {
+                    let population = report.populations.get(*species_key).copied();
+                    write!(writer, ",{}", population.unwrap_or(0))?;
+                }
+            }
+            None => write!(writer, ",,{}", ",".repeat(species.len()))?,
+        }
+        writeln!(writer)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Quote a CSV field when needed
+fn csv_field(text: &str) -> String {
+    if text.contains([',', '"', '\n']) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn range(min: toml::Value, max: toml::Value, steps: Option<u32>) -> Axis {
+        Axis::Range { min, max, steps }
+    }
+
+    #[test]
+    fn grids_space_range_values_evenly() {
+        let floats = range(0.0.into(), 1.0.into(), Some(5)).grid("a").unwrap();
+        assert_eq!(floats, [0.0, 0.25, 0.5, 0.75, 1.0].map(toml::Value::Float));
+        let integers = range(0.into(), 10.into(), Some(3)).grid("a").unwrap();
+        assert_eq!(integers, [0, 5, 10].map(toml::Value::Integer));
+        let single = range(2.0.into(), 4.0.into(), Some(1)).grid("a").unwrap();
+        assert_eq!(single, [toml::Value::Float(2.0)]);
+    }
+
+    #[test]
+    fn grids_reject_invalid_axes() {
+        assert!(range(0.0.into(), 1.0.into(), None).grid("a").is_err());
+        assert!(range(1.0.into(), 0.0.into(), Some(2)).grid("a").is_err());
+        assert!(range("x".into(), 1.0.into(), Some(2)).grid("a").is_err());
+        assert!(range(0.0.into(), 1.0.into(), Some(0)).grid("a").is_err());
+        assert!(Axis::Values(Vec::new()).grid("a").is_err());
+    }
+
+    #[test]
+    fn samples_stay_within_their_axis() {
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        for _ in 0..100 {
+            let integer = range(1.into(), 3.into(), None)
+                .sample("a", &mut rng)
+                .unwrap();
+            assert!((1..=3).contains(&integer.as_integer().unwrap()));
+            let float = range(0.5.into(), 1.5.into(), None)
+                .sample("a", &mut rng)
+                .unwrap();
+            assert!((0.5..=1.5).contains(&float.as_float().unwrap()));
+            let values = vec!["x".into(), "y".into()];
+            let value = Axis::Values(values.clone()).sample("a", &mut rng).unwrap();
+            assert!(values.contains(&value));
+        }
+        assert!(Axis::Values(Vec::new()).sample("a", &mut rng).is_err());
+    }
+
+    #[test]
+    fn csv_fields_are_quoted_when_needed() {
+        assert_eq!(csv_field("plain"), "plain");
+        assert_eq!(csv_field("a,b"), "\"a,b\"");
+        assert_eq!(csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+        assert_eq!(csv_field("two\nlines"), "\"two\nlines\"");
+    }
+}
@@ -1,28 +1,371 @@
 use avian2d::prelude::*;
 use bevy::{
     app::ScheduleRunnerPlugin, diagnostic::FrameCount, prelude::*, render::RenderPlugin,
-    winit::WinitPlugin,
+    time::TimeUpdateStrategy, winit::WinitPlugin,
 };
 use bevy_capture::{CameraTargetHeadless, CaptureBundle};
 use chrono::{DateTime, Utc};
+use clap::Parser;
 use rand::prelude::*;
-use std::fs;
+use serde::Serialize;
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    process::ExitCode,
+    time::Duration,
+};
 
+mod arena;
+mod batch;
+mod brain;
+mod cli;
 mod components;
 mod config;
+mod experiment;
+mod replay;
 mod resources;
 mod systems;
 
+use arena::*;
+use cli::*;
 use components::*;
 use config::*;
+use replay::*;
 use resources::*;
 use systems::*;
 
 /// Main
-fn main() {
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    let result = match cli.action.unwrap_or(Action::Run(cli.run)) {
+        Action::Run(args) => run(args),
+        Action::Validate(args) => validate(args),
+        Action::ListBiomes(args) => list_biomes(args),
+        Action::Batch(args) => batch::run_batch(args),
+        Action::Replay(args) => render_replay(args),
+        Action::Experiment(args) => experiment::run_experiment(args),
+    };
+
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Run a simulation, captured to video unless headless
+fn run(args: RunArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let runtime_config = RuntimeConfig::load(&args.config.config, args.config.lore.as_deref())?;
+    runtime_config.check_no_external_brain("command line runs")?;
+
+    // Command line overrides the simulation config, which overrides the defaults
+    let meta = &runtime_config.simulation.simulation;
+    let mut settings = RunSettings {
+        seed: args.seed.or(meta.seed).unwrap_or_else(rand::random),
+        duration: args
+            .duration
+            .or(runtime_config.simulation.stop.max_duration)
+            .unwrap_or(MAX_DURATION),
+        framerate: args.fps.or(meta.framerate).unwrap_or(FRAMERATE),
+        output_dir: args
+            .output
+            .or_else(|| meta.output_dir.clone())
+            .unwrap_or_else(|| PathBuf::from(OUTPUTS_DIR)),
+        name: args.name,
+        snapshot_at: args.snapshot_at,
+        restored_from: args.restore.clone(),
+        replay: args.headless && !args.no_replay,
+    };
+    if settings.duration <= 0.0 || settings.framerate <= 0.0 {
+        return Err("Duration and frame rate must be strictly positive".into());
+    }
+    if settings.framerate.fract() != 0.0 {
+        return Err(format!(
+            "Frame rate must be a whole number of frames per second, not {}",
+            settings.framerate
+        )
+        .into());
+    }
+
+    // Resume from a snapshot taken in the same biome and at the same frame rate
+    let snapshot = args
+        .restore
+        .as_deref()
+        .map(WorldSnapshot::load)
+        .transpose()?;
+    let mut rng = SimulationRng::new(settings.seed);
+    if let Some(snapshot) = &snapshot {
+        if snapshot.biome != runtime_config.simulation.biome {
+            return Err(format!(
+                "Snapshot was taken in biome '{}', not '{}'",
+                snapshot.biome, runtime_config.simulation.biome
+            )
+            .into());
+        }
+        if snapshot.framerate != settings.framerate {
+            return Err(format!(
+                "Snapshot was taken at {} frames per second, not {}",
+                snapshot.framerate, settings.framerate
+            )
+            .into());
+        }
+        for entity in &snapshot.entities {
+            if runtime_config.species.get(&entity.species).is_none() {
+                return Err(format!(
+                    "Snapshot species '{}' not found in biome '{}'",
+                    entity.species, snapshot.biome
+                )
+                .into());
+            }
+        }
+        let current_biome = &runtime_config.lore.biomes[&snapshot.biome];
+        for seed in &snapshot.seeds {
+            if current_biome
+                .species
+                .get(&seed.species)
+                .is_none_or(|species_data| species_data.seeds.is_none())
+            {
+                return Err(format!(
+                    "Snapshot seeds of species '{}' but it disperses no seeds in biome '{}'",
+                    seed.species, snapshot.biome
+                )
+                .into());
+            }
+        }
+        if args.seed.is_none() {
+            settings.seed = snapshot.seed;
+            rng = SimulationRng::restore(snapshot.seed, &snapshot.rng_positions)?;
+        }
+        println!("Restoring snapshot at frame {}", snapshot.frame);
+    }
+    println!("Seed: {}", settings.seed);
+
+    // Nutrient field over the water area
+    let mut nutrients = runtime_config
+        .simulation
+        .nutrients
+        .as_ref()
+        .map(|nutrients| {
+            let layout = Layout::new();
+            NutrientField::new(
+                Vec2::new(-layout.half_width, layout.middle_wall_y),
+                Vec2::new(WINDOW_WIDTH, layout.half_height - layout.middle_wall_y),
+                nutrients.cell_size,
+                nutrients.initial,
+            )
+        });
+    if let Some(snapshot) = &snapshot
+        && let Some(field) = nutrients.as_mut()
+        && let Some(values) = &snapshot.nutrients
+    {
+        field.restore(values.clone())?;
+    }
+    let arena = Arena::new(&runtime_config.arena)?;
+
     let mut app = App::new();
+    let time_step = Duration::from_secs_f32(settings.time_step());
+
+    if args.headless {
+        // Simulation only, stepped by one frame per update as fast as the CPU allows
+        app.add_plugins((
+            MinimalPlugins.set(ScheduleRunnerPlugin::run_loop(Duration::ZERO)),
+            TransformPlugin,
+            AssetPlugin::default(),
+            PhysicsPlugins::default(),
+        ))
+        .init_asset::<Mesh>()
+        .insert_resource(TimeUpdateStrategy::ManualDuration(time_step))
+        .insert_resource(Time::<Fixed>::from_duration(time_step))
+        .add_systems(
+            Update,
+            (
+                record_replay.run_if(resource_exists::<ReplayWriter>),
+                exit_on_stop,
+            )
+                .chain()
+                .after(save_snapshots),
+        );
+    } else {
+        // Rendering and video capture
+        app.add_plugins((
+            DefaultPlugins
+                .build()
+                .disable::<WinitPlugin>()
+                .set(RenderPlugin {
+                    synchronous_pipeline_compilation: true,
+                    ..default()
+                }),
+            PhysicsPlugins::default(),
+            ScheduleRunnerPlugin {
+                run_mode: bevy::app::RunMode::Loop { wait: None },
+            },
+            bevy_capture::CapturePlugin,
+        ))
+        .add_systems(
+            Startup,
+            (spawn_camera, spawn_hud, spawn_debugger)
+                .chain()
+                .after(spawn_entities),
+        )
+        .add_systems(
+            Update,
+            (
+                attach_terrain_visuals,
+                attach_entity_visuals,
+                visualize_raycast,
+                update_hud,
+                capture_frame,
+                update_debugger,
+            )
+                .chain()
+                .after(record_statistics),
+        )
+        .insert_resource(ClearColor(Color::srgb(0.0, 0.0, 0.0)));
+    }
 
     // Simulation
+    app.add_systems(
+        Startup,
+        (
+            setup,
+            generate_world,
+            spawn_entities.run_if(not(resource_exists::<WorldSnapshot>)),
+            restore_snapshot.run_if(resource_exists::<WorldSnapshot>),
+        )
+            .chain(),
+    )
+    .add_systems(
+        Update,
+        (
+            // Energy
+            (
+                idle_energy,
+                plant_regeneration_system,
+                diffuse_nutrients.run_if(resource_exists::<NutrientField>),
+            )
+                .chain(),
+            // Decision & movement
+            (
+                update_vision_system,
+                decision_system,
+                apply_movement_system,
+                bush_drag,
+                movement_energy,
+            )
+                .chain(),
+            // Life & death
+            (
+                ageing,
+                collision_kill_system,
+                scavenging,
+                reproduction,
+                mating,
+                seed_drift,
+                germination,
+                death,
+                carcass_decay,
+            )
+                .chain(),
+            manual_physics_step,
+            // Outputs
+            (
+                check_stop_conditions,
+                write_stop_report,
+                export_networks.after(check_stop_conditions),
+                record_statistics,
+                record_traits,
+                save_snapshots,
+            )
+                .chain(),
+        )
+            .chain(),
+    )
+    // Configuration
+    .insert_resource(rng)
+    .init_resource::<StopState>()
+    .init_resource::<ExternalIntents>()
+    .insert_resource(settings)
+    .insert_resource(arena)
+    .insert_resource(runtime_config.species.clone())
+    .insert_resource(GameConfig {
+        runtime: runtime_config,
+    })
+    // Avian's physics
+    .insert_resource(Gravity(Vec2::ZERO))
+    // Miscellaneous
+    .init_resource::<FrameCount>();
+
+    if let Some(nutrients) = nutrients {
+        app.insert_resource(nutrients);
+    }
+    if let Some(snapshot) = snapshot {
+        app.insert_resource(snapshot);
+    }
+
+    // Run
+    match app.run() {
+        AppExit::Success => Ok(()),
+        AppExit::Error(code) => Err(format!("Simulation exited with code {}", code).into()),
+    }
+}
+
+/// Render a recorded replay to video
+fn render_replay(args: ReplayArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let runtime_config = RuntimeConfig::load(&args.config.config, args.config.lore.as_deref())?;
+    let reader = ReplayReader::open(&args.replay)?;
+    let header = &reader.header;
+    if header.biome != runtime_config.simulation.biome {
+        return Err(format!(
+            "Replay was recorded in biome '{}', not '{}'",
+            header.biome, runtime_config.simulation.biome
+        )
+        .into());
+    }
+
+    // Map the replay species to the species of the lore
+    let mut species = Vec::new();
+    for species_key in &header.species {
+        let id = runtime_config.species.get(species_key).ok_or(format!(
+            "Replay species '{}' not found in biome '{}'",
+            species_key, header.biome
+        ))?;
+        species.push(id);
+    }
+
+    // The video is written next to the replay unless an output is given
+    let replay_dir = args.replay.parent().unwrap_or(Path::new(""));
+    let name = args.name.unwrap_or_else(|| {
+        replay_dir.file_name().map_or("replay".to_string(), |name| {
+            name.to_string_lossy().into_owned()
+        })
+    });
+    let video_dir = match &args.output {
+        Some(output) => output.join(&name),
+        None => replay_dir.to_path_buf(),
+    };
+    fs::create_dir_all(&video_dir)?;
+
+    let settings = RunSettings {
+        seed: header.seed,
+        duration: runtime_config
+            .simulation
+            .stop
+            .max_duration
+            .unwrap_or(MAX_DURATION),
+        framerate: header.framerate,
+        output_dir: video_dir.clone(),
+        name: Some(name.clone()),
+        snapshot_at: Vec::new(),
+        restored_from: None,
+        replay: false,
+    };
+
+    let arena = Arena::new(&runtime_config.arena)?;
+
+    let mut app = App::new();
     app.add_plugins((
         DefaultPlugins
             .build()
@@ -39,71 +382,162 @@ fn main() {
     ))
     .add_systems(
         Startup,
-        (
-            setup,
-            generate_world,
-            spawn_entities,
-            spawn_hud,
-            spawn_debugger,
-        )
-            .chain(),
+        (generate_world, spawn_camera, spawn_hud, spawn_debugger).chain(),
     )
     .add_systems(
         Update,
         (
-            idle_energy,
-            plant_regeneration_system,
-            update_vision_system,
-            vision_analysis_system,
-            apply_movement_system,
-            movement_energy,
-            collision_kill_system,
-            reproduction,
-            death,
-            visualize_raycast,
+            play_replay,
+            attach_terrain_visuals,
+            attach_entity_visuals,
             update_hud,
-            manual_physics_step,
             capture_frame,
             update_debugger,
         )
             .chain(),
     )
-    // Avian's physics
-    .insert_resource(Gravity(Vec2::ZERO))
-    // Miscellaneous
     .insert_resource(ClearColor(Color::srgb(0.0, 0.0, 0.0)))
+    .insert_resource(ReplayPlayback {
+        reader,
+        species,
+        entities: HashMap::new(),
+        alive: Vec::new(),
+        next_id: 0,
+    })
+    .insert_resource(SimulationMetadata {
+        path_dir: video_dir.display().to_string(),
+        name,
+    })
+    .init_resource::<StopState>()
+    .insert_resource(settings)
+    .insert_resource(arena)
+    .insert_resource(runtime_config.species.clone())
+    .insert_resource(GameConfig {
+        runtime: runtime_config,
+    })
+    .insert_resource(Gravity(Vec2::ZERO))
     .init_resource::<FrameCount>();
 
-    // Run
-    app.run();
+    match app.run() {
+        AppExit::Success => Ok(()),
+        AppExit::Error(code) => Err(format!("Replay rendering exited with code {}", code).into()),
+    }
 }
 
-/// Setup
-fn setup(mut commands: Commands, mut images: ResMut<Assets<Image>>) {
-    // Load runtime configuration
-    let runtime_config = RuntimeConfig::load().expect("Failed to load configuration");
+/// Validate configuration
+fn validate(args: ConfigArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let runtime_config = RuntimeConfig::load(&args.config, args.lore.as_deref())?;
+    let simulation = &runtime_config.simulation;
+    // Decode the arena mask, if any
+    Arena::new(&runtime_config.arena)?;
 
-    // Insert configuration as resource
-    commands.insert_resource(GameConfig {
-        runtime: runtime_config,
-    });
+    println!("Configuration {} is valid", args.config.display());
+    println!("{}", runtime_config.get_title());
+    let mut populations: Vec<_> = simulation.populations.iter().collect();
+    populations.sort();
+    for (species_key, population) in populations {
+        println!("  {}: {}", species_key, population);
+    }
+
+    Ok(())
+}
+
+/// List lore biomes
+fn list_biomes(args: LoreArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let lore = match &args.lore {
+        Some(path) => LoreConfig::load(path)?,
+        None => LoreConfig::embedded()?,
+    };
+
+    let mut biomes: Vec<_> = lore.biomes.iter().collect();
+    biomes.sort_by_key(|(biome_key, _)| *biome_key);
+    for (biome_key, biome) in biomes {
+        let mut species: Vec<_> = biome.species.keys().map(String::as_str).collect();
+        species.sort();
+        println!(
+            "{} - {} ({}): {}",
+            biome_key,
+            biome.name,
+            biome.biome_type,
+            species.join(", ")
+        );
+    }
+
+    Ok(())
+}
 
+/// Record of a run, written to its output folder so that it can be reproduced
+#[derive(Serialize)]
+struct RunRecord<'a> {
+    version: &'a str,
+    run: &'a RunSettings,
+    simulation: &'a SimulationConfig,
+}
+
+/// Setup
+fn setup(
+    mut commands: Commands,
+    settings: Res<RunSettings>,
+    config: Res<GameConfig>,
+    species_registry: Res<SpeciesRegistry>,
+) {
     // Create outputs directories
-    // Get and format date
+    // Get and format date, unless the simulation is named
     let now: DateTime<Utc> = Utc::now();
-    let str_date = now.format("sim_%Y-%m-%d-%H-%M-%SZ").to_string();
+    let sim_name = settings
+        .name
+        .clone()
+        .unwrap_or_else(|| now.format("sim_%Y-%m-%d-%H-%M-%SZ").to_string());
 
     // Generate directories
-    let sim_dir = format!("./outputs/{}", str_date);
+    let sim_dir = settings.output_dir.join(&sim_name);
     fs::create_dir_all(&sim_dir).expect("Failed to create simulation directory.");
 
+    // Record the seed and configuration of the run
+    let record = RunRecord {
+        version: env!("CARGO_PKG_VERSION"),
+        run: &settings,
+        simulation: &config.runtime.simulation,
+    };
+    fs::write(
+        sim_dir.join("run.toml"),
+        toml::to_string(&record).expect("Failed to serialize run record."),
+    )
+    .expect("Failed to write run record.");
+
+    // Open the statistics file
+    let statistics = StatisticsWriter::create(&sim_dir.join("statistics.csv"), &species_registry)
+        .expect("Failed to create statistics file.");
+    commands.insert_resource(statistics);
+    let traits =
+        TraitsWriter::create(&sim_dir.join("traits.csv")).expect("Failed to create traits file.");
+    commands.insert_resource(traits);
+
+    // Open the replay file
+    if settings.replay {
+        let header = ReplayHeader {
+            framerate: settings.framerate,
+            seed: settings.seed,
+            biome: config.runtime.simulation.biome.clone(),
+            species: species_registry
+                .iter()
+                .map(|species| species_registry.key(species).to_string())
+                .collect(),
+        };
+        let replay = ReplayWriter::create(&sim_dir.join("replay.bin"), &header)
+            .expect("Failed to create replay file.");
+        commands.insert_resource(replay);
+    }
+
     // Insert as a resource
     commands.insert_resource(SimulationMetadata {
-        path_dir: sim_dir,
-        name: str_date,
+        path_dir: sim_dir.display().to_string(),
+        name: sim_name,
     });
+}
 
-    // Headless camera
+/// Headless render camera
+fn spawn_camera(mut commands: Commands, mut images: ResMut<Assets<Image>>) {
     commands.spawn((
         Camera2d,
         Camera::default().target_headless(WINDOW_WIDTH as u32, WINDOW_HEIGHT as u32, &mut images),
@@ -112,7 +546,7 @@ fn setup(mut commands: Commands, mut images: ResMut<Assets<Image>>) {
 }
 
 /// Simulation
-fn generate_world(mut commands: Commands, config: Res<GameConfig>) {
+fn generate_world(mut commands: Commands, config: Res<GameConfig>, arena: Res<Arena>) {
     let wall_restitution = 0.7;
     let half_w = WINDOW_WIDTH / 2.0;
     let half_h = WINDOW_HEIGHT / 2.0;
@@ -135,9 +569,15 @@ fn generate_world(mut commands: Commands, config: Res<GameConfig>) {
         current_biome.environment.water_color[1],
         current_biome.environment.water_color[2],
     );
+    // Land around the basin, if any
+    let background_color = if arena.basin.is_some() {
+        walls_color
+    } else {
+        water_color
+    };
     commands.spawn((
         Sprite {
-            color: water_color,
+            color: background_color,
             custom_size: Some(Vec2::new(WINDOW_WIDTH, WINDOW_WIDTH)),
             ..default()
         },
@@ -262,50 +702,60 @@ fn generate_world(mut commands: Commands, config: Res<GameConfig>) {
         Transform::from_xyz(0.0, -250.0 - 154.0 / 2.0, Z_HUD),
         Restitution::new(wall_restitution),
     ));
-}
 
-/// Helper struct for consistent layout calculations
-struct Layout {
-    half_width: f32,
-    half_height: f32,
-    middle_wall_y: f32,
-}
+    // Basin
+    let environment = &current_biome.environment;
+    if let Some(basin) = &arena.basin {
+        commands.spawn((
+            Terrain::Polygon(basin.clone()),
+            EntityColor::new(
+                environment.water_color[0],
+                environment.water_color[1],
+                environment.water_color[2],
+            ),
+            RigidBody::Static,
+            Collider::polyline(basin.clone(), Some(outline_edges(basin))),
+            Transform::from_xyz(0.0, 0.0, Z_BASIN),
+            Restitution::new(wall_restitution),
+        ));
+    }
 
-impl Layout {
-    fn new() -> Self {
-        let half_width = WINDOW_WIDTH / 2.0;
-        let half_height = WINDOW_HEIGHT / 2.0;
-        let middle_wall_y = -half_height + WALLS_THICKNESS / 2.0 + (WINDOW_HEIGHT - WINDOW_WIDTH);
-        Self {
-            half_width,
-            half_height,
-            middle_wall_y,
-        }
+    // Obstacles
+    for (position, terrain) in &arena.obstacles {
+        let collider = match terrain {
+            Terrain::Circle(radius) => Collider::circle(*radius),
+            Terrain::Rectangle(size) => Collider::rectangle(size.x, size.y),
+            Terrain::Polygon(points) => {
+                Collider::convex_decomposition(points.clone(), outline_edges(points))
+            }
+        };
+        commands.spawn((
+            terrain.clone(),
+            EntityColor::new(
+                environment.frame_color[0],
+                environment.frame_color[1],
+                environment.frame_color[2],
+            ),
+            RigidBody::Static,
+            collider,
+            Transform::from_translation(position.extend(Z_HUD)),
+            Restitution::new(wall_restitution),
+        ));
     }
 }
 
 fn spawn_entities(
     mut commands: Commands,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<ColorMaterial>>,
     config: Res<GameConfig>,
+    species_registry: Res<SpeciesRegistry>,
+    arena: Res<Arena>,
+    mut rng: ResMut<SimulationRng>,
 ) {
-    let mut rng = rand::rng();
+    let rng = rng.stream(RngStream::Spawn);
     let layout = Layout::new();
 
     let walls_paddings = WALLS_THICKNESS * 2.0 + 8.0;
 
-    let entity_bundle = (
-        RigidBody::Dynamic,
-        Restitution::new(0.2), // Bouncing restitution
-        Friction::new(0.5),
-        LinearDamping(LINEAR_DAMPING),
-        AngularDamping(ANGULAR_DAMPING),
-        ColliderDensity(1.0), // Add density so mass is computed from collider
-        CollisionEventsEnabled,
-        Consumable,
-    );
-
     // Get current biome data
     let current_biome = config
         .runtime
@@ -314,29 +764,24 @@ fn spawn_entities(
         .get(&config.runtime.simulation.biome)
         .expect("Current biome not found in lore config");
 
-    // Spawn entities dynamically based on config
-    for (species_key, species_data) in &current_biome.species {
+    // Spawn entities dynamically based on config, in a stable order so seeds are reproducible
+    let mut species_entries: Vec<_> = current_biome.species.iter().collect();
+    species_entries.sort_by_key(|(species_key, _)| *species_key);
+    for (species_key, species_data) in species_entries {
         if let Some(population) = config.runtime.simulation.populations.get(species_key) {
             if *population == 0 {
                 continue;
             }
 
             let params = EntitySpawnParams::from_species_data(species_data);
-            let species_enum = Species::from_string(species_key)
-                .unwrap_or_else(|| panic!("Unknown species: {}", species_key));
-            let entity_color = EntityColor::new(params.color[0], params.color[1], params.color[2]);
-
-            // Create hunting relationships
-            let mut hunts = Vec::new();
-            for prey_name in &species_data.eats {
-                if let Some(prey_species) = Species::from_string(prey_name) {
-                    hunts.push(prey_species);
-                }
-            }
+            let species = species_registry
+                .get(species_key)
+                .expect("Species not registered from lore config");
 
             // Entity spawn
-            let circle = Circle::new(params.size);
-            for _i in 0..*population {
+            let imported = config.runtime.networks.get(species_key);
+            let mut skipped = 0;
+            for i in 0..*population as usize {
                 let rand_speed_factor = rng.random_range(0.3..1.0);
                 let angle = rng.random_range(0.0..std::f32::consts::TAU);
                 let rotation = Quat::from_rotation_z(angle);
@@ -345,66 +790,311 @@ fn spawn_entities(
                 let entity_padding = walls_paddings + params.size;
                 let spawn_width = WINDOW_WIDTH - 2.0 * entity_padding;
                 let spawn_height = WINDOW_WIDTH - 2.0 * entity_padding;
-
-                let mut entity_commands = commands.spawn((
-                    entity_bundle.clone(),
-                    entity_color.clone(),
-                    species_enum,
-                    Energy::new(params.initial_energy, params.max_energy),
-                    Size::new(params.size),
-                    Speed::new(params.max_speed * rand_speed_factor),
-                    Collider::circle(params.size),
-                    Mesh2d(meshes.add(circle)),
-                    MeshMaterial2d(materials.add(entity_color.value())),
-                    Transform::from_xyz(
+                let mut draw_position = || {
+                    Vec2::new(
                         rng.random::<f32>() * spawn_width - layout.half_width + entity_padding,
                         rng.random::<f32>() * spawn_height + layout.middle_wall_y + entity_padding,
-                        Z_ENTITIES,
                     )
-                    .with_rotation(rotation),
-                    LinearVelocity(Vec2::new(
-                        params.max_speed * rand_speed_factor * (rng.random::<f32>() * 2.0 - 1.0),
-                        params.max_speed * rand_speed_factor * (rng.random::<f32>() * 2.0 - 1.0),
-                    )),
-                    MovementIntent::default(),
-                ));
+                };
+                // Redraw positions on land or obstacles, skipping the organism when the
+                // arena is too crowded to fit it
+                let Some(position) = (0..MAX_SPAWN_ATTEMPTS)
+                    .map(|_| draw_position())
+                    .find(|position| arena.contains(*position, params.size))
+                else {
+                    skipped += 1;
+                    continue;
+                };
+                let (x, y) = (position.x, position.y);
+                let velocity = Vec2::new(
+                    params.max_speed * rand_speed_factor * (rng.random::<f32>() * 2.0 - 1.0),
+                    params.max_speed * rand_speed_factor * (rng.random::<f32>() * 2.0 - 1.0),
+                );
 
-                // Add type-specific components
-                match species_data.species_type.as_str() {
-                    "Flora" => {
-                        entity_commands.insert((
-                            Name::new("Plant"),
-                            Photosynthesis::new(params.photosynthesis_rate.unwrap_or(5.0)),
-                        ));
-                    }
-                    "Fauna" => {
-                        entity_commands.insert(Name::new("Fauna"));
-
-                        // Add hunter component if this species eats others
-                        if !hunts.is_empty() {
-                            entity_commands.insert(Hunter::new(hunts.clone()));
+                let mut entity_commands = spawn_organism(
+                    &mut commands,
+                    species,
+                    species_data,
+                    &params,
+                    params.genome(params.max_speed * rand_speed_factor),
+                    &species_registry,
+                );
+                entity_commands.insert((
+                    Energy::new(params.initial_energy, params.max_energy),
+                    Transform::from_xyz(x, y, Z_ENTITIES).with_rotation(rotation),
+                    LinearVelocity(velocity),
+                ));
+                if let Some(mating_types) = params.mating_types {
+                    entity_commands.insert(MatingType(rng.random_range(0..mating_types)));
+                }
+                // Founders of neural species take the imported networks in turn, or else
+                // random ones
+                if let Brain::Neural { network, .. } = &params.brain {
+                    let network = match imported {
+                        Some(networks) if !networks.is_empty() => {
+                            networks[i % networks.len()].clone()
                         }
+                        _ => Network::random(network.hidden, rng),
+                    };
+                    entity_commands.insert(params.brain.with_network(network));
+                }
+            }
+            if skipped > 0 {
+                eprintln!(
+                    "Skipped {} of {} {} organisms, no free space found in the arena",
+                    skipped, population, species_key
+                );
+            }
+        }
+    }
+}
 
-                        // Add active mover for non-plant species
-                        if params.is_active_mover {
-                            entity_commands.insert(ActiveMover);
-                        }
+/// Spawn the entities of a world snapshot in place of the configured populations
+fn restore_snapshot(
+    mut commands: Commands,
+    config: Res<GameConfig>,
+    species_registry: Res<SpeciesRegistry>,
+    snapshot: Res<WorldSnapshot>,
+    mut rng: ResMut<SimulationRng>,
+) {
+    let rng = rng.stream(RngStream::Spawn);
+    let current_biome = config
+        .runtime
+        .lore
+        .biomes
+        .get(&config.runtime.simulation.biome)
+        .expect("Current biome not found in lore config");
 
-                        // Add vision with 3 rays at ±30°
-                        entity_commands.insert(Vision::new(
-                            params.detection_range,
-                            360,
-                            360.0_f32.to_radians(),
-                        ));
-                        entity_commands.insert(VisionResults::default());
-                    }
-                    _ => {
-                        entity_commands.insert(Name::new("Unknown"));
-                    }
+    // Snapshot species were checked against the lore before the run started
+    for entity in &snapshot.entities {
+        let species = species_registry
+            .get(&entity.species)
+            .expect("Snapshot species not registered from lore config");
+        if let Some(carcass) = &entity.carcass {
+            spawn_carcass(
+                &mut commands,
+                &config.runtime.simulation.carcasses,
+                species,
+                carcass.color,
+                entity.size,
+                Energy::new(entity.energy, entity.max_energy),
+            )
+            .insert((
+                Transform::from_xyz(entity.position[0], entity.position[1], Z_CARCASSES)
+                    .with_rotation(Quat::from_rotation_z(entity.rotation)),
+                LinearVelocity(Vec2::from_array(entity.linear_velocity)),
+                AngularVelocity(entity.angular_velocity),
+            ));
+            continue;
+        }
+        let species_data = &current_biome.species[&entity.species];
+        let params = EntitySpawnParams::from_species_data(species_data);
+
+        let genome = entity
+            .genome
+            .clone()
+            .unwrap_or_else(|| params.genome(entity.speed));
+        let mut entity_commands = spawn_organism(
+            &mut commands,
+            species,
+            species_data,
+            &params,
+            genome,
+            &species_registry,
+        );
+        entity_commands.insert((
+            Energy::new(entity.energy, entity.max_energy),
+            Size::new(entity.size),
+            Speed::new(entity.speed),
+            Collider::circle(entity.size),
+            Transform::from_xyz(entity.position[0], entity.position[1], Z_ENTITIES)
+                .with_rotation(Quat::from_rotation_z(entity.rotation)),
+            LinearVelocity(Vec2::from_array(entity.linear_velocity)),
+            AngularVelocity(entity.angular_velocity),
+            Age(entity.age),
+        ));
+
+        // Restore the state that may have diverged from the lore
+        match &entity.hunts {
+            Some(hunts) => {
+                let hunts = hunts
+                    .iter()
+                    .filter_map(|prey_name| species_registry.get(prey_name))
+                    .collect();
+                entity_commands.insert(Hunter::new(hunts));
+            }
+            None => {
+                entity_commands.remove::<Hunter>();
+            }
+        }
+        match &entity.vision {
+            Some(vision) => {
+                entity_commands.insert((
+                    Vision::new(vision.detection_range, vision.nb_rays, vision.field_of_view),
+                    VisionResults::default(),
+                ));
+            }
+            None => {
+                entity_commands.remove::<(Vision, VisionResults)>();
+            }
+        }
+        match entity.photosynthesis {
+            Some(rate) => {
+                entity_commands.insert(Photosynthesis::new(rate));
+            }
+            None => {
+                entity_commands.remove::<Photosynthesis>();
+            }
+        }
+        // Snapshots without mating types draw them as the spawned founders do, so that
+        // sexual species can still mate
+        match (entity.mating_type, params.mating_types) {
+            (Some(mating_type), _) => {
+                entity_commands.insert(MatingType(mating_type));
+            }
+            (None, Some(mating_types)) => {
+                entity_commands.insert(MatingType(rng.random_range(0..mating_types)));
+            }
+            (None, None) => {}
+        }
+        if let Some(network) = &entity.network
+            && params.brain.network().is_some()
+        {
+            entity_commands.insert(params.brain.with_network(network.clone()));
+        }
+        if let Some(behaviour) = &entity.behaviour
+            && let Brain::Behaviour {
+                params: behaviour_params,
+                ..
+            } = &params.brain
+        {
+            entity_commands.insert(Brain::Behaviour {
+                params: *behaviour_params,
+                state: behaviour.state,
+                elapsed: behaviour.elapsed,
+                heading: Vec2::from_array(behaviour.heading),
+            });
+        }
+    }
+
+    for seed in &snapshot.seeds {
+        let species = species_registry
+            .get(&seed.species)
+            .expect("Snapshot species not registered from lore config");
+        let params = EntitySpawnParams::from_species_data(&current_biome.species[&seed.species]);
+        restore_seed(&mut commands, seed, species, &params);
+    }
+
+    // Resume the frame counter, the snapshot is no longer needed
+    commands.insert_resource(FrameCount(snapshot.frame));
+    commands.remove_resource::<WorldSnapshot>();
+}
+
+/// Spawn an organism with the components derived from its genome and species lore. Its
+/// state (energy, position and velocity) is left to the caller
+fn spawn_organism<'a>(
+    commands: &'a mut Commands,
+    species: Species,
+    species_data: &SpeciesData,
+    params: &EntitySpawnParams,
+    genome: Genome,
+    species_registry: &SpeciesRegistry,
+) -> EntityCommands<'a> {
+    let mut entity_commands = commands.spawn((
+        RigidBody::Dynamic,
+        Restitution::new(0.2), // Bouncing restitution
+        Friction::new(0.5),
+        LinearDamping(LINEAR_DAMPING),
+        AngularDamping(ANGULAR_DAMPING),
+        CollisionEventsEnabled,
+        Consumable,
+        EntityColor::new(genome.color[0], genome.color[1], genome.color[2]),
+        species,
+        Size::new(genome.size),
+        Speed::new(genome.speed),
+        Collider::circle(genome.size),
+        ColliderDensity(params.density), // Add density so mass is computed from collider
+        MovementIntent::default(),
+        Age::default(),
+    ));
+
+    // Add type-specific components
+    match species_data.species_type.as_str() {
+        "Flora" => {
+            entity_commands.insert((
+                Name::new("Plant"),
+                Photosynthesis::new(params.photosynthesis_rate.unwrap_or(5.0)),
+            ));
+            if let Some(rooted) = params.rooted {
+                root(&mut entity_commands, rooted);
+            }
+            if let Some(dispersal) = params.dispersal {
+                entity_commands.insert(dispersal);
+            }
+        }
+        "Fauna" => {
+            entity_commands.insert(Name::new("Fauna"));
+
+            // Add hunter component if this species eats others
+            let hunts: Vec<Species> = species_data
+                .eats
+                .iter()
+                .filter_map(|prey_name| species_registry.get(prey_name))
+                .collect();
+            if !hunts.is_empty() {
+                entity_commands.insert(Hunter::new(hunts));
+            }
+
+            // Add scavenger component if this species eats carcasses
+            let scavenges: Vec<Species> = species_data
+                .scavenges
+                .iter()
+                .filter_map(|carrion_name| species_registry.get(carrion_name))
+                .collect();
+            if !scavenges.is_empty() {
+                entity_commands.insert(Scavenger::new(scavenges));
+            }
+
+            // Add active mover for non-plant species
+            if params.is_active_mover {
+                entity_commands.insert((
+                    ActiveMover,
+                    Locomotion::new(params.turn_responsiveness, params.acceleration),
+                ));
+            }
+
+            // Add vision
+            entity_commands.insert(Vision::new(
+                genome.detection_range,
+                params.nb_rays,
+                genome.field_of_view,
+            ));
+            entity_commands.insert(VisionResults::default());
+
+            // Add steering weights, with per-species overrides from the lore
+            let mut steering = SteeringWeights::new(
+                genome.boldness,
+                -genome.fearfulness,
+                genome.sociability,
+                WEIGHT_NEUTRAL,
+                params.mate_weight,
+            )
+            .with_strategies(params.hunting, params.fleeing)
+            .with_schooling(params.schooling);
+            for (target_name, weight) in &species_data.weights {
+                if let Some(target_species) = species_registry.get(target_name) {
+                    steering = steering.with_override(target_species, *weight);
                 }
             }
+            entity_commands.insert((steering, params.brain.clone()));
+        }
+        _ => {
+            entity_commands.insert(Name::new("Unknown"));
         }
     }
+    entity_commands.insert(genome);
+
+    entity_commands
 }
 
 /// HUD
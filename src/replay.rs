@@ -0,0 +1,342 @@
+use bevy::prelude::*;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+/// Binary replay layout, little-endian:
+/// - header: magic, format version, frame rate (f32), seed (u32), biome key, species keys
+/// - one record per frame: frame (u32), deaths, births, states of the living entities
+///
+/// Entities are identified by their order of birth. States are listed in birth order of
+/// the living entities, deaths removing them from that order. Carcasses are entities of
+/// their own, born when their organism dies, and so are seeds, dying when they germinate.
+const MAGIC: &[u8; 8] = b"TEEMREPL";
+const FORMAT_VERSION: u16 = 4;
+/// Bit of the birth species index flagging a carcass
+const CARCASS_FLAG: u16 = 0x8000;
+/// Bit of the birth species index flagging a seed
+const SEED_FLAG: u16 = 0x4000;
+/// Positions are stored in sixteenths of a pixel
+const POSITION_SCALE: f32 = 16.0;
+
+pub struct ReplayHeader {
+    pub framerate: f32,
+    pub seed: u32,
+    pub biome: String,
+    pub species: Vec<String>,
+}
+
+pub struct ReplayBirth {
+    /// Index in the header species
+    pub species: u16,
+    pub carcass: bool,
+    pub seed: bool,
+    pub size: f32,
+    pub max_energy: f32,
+    /// Linear RGB colour
+    pub color: [f32; 3],
+}
+
+#[derive(Clone, Copy)]
+pub struct ReplayState {
+    pub position: Vec2,
+    /// Rotation around the z axis in radians
+    pub rotation: f32,
+    /// Energy relative to the maximum energy
+    pub energy_ratio: f32,
+}
+
+#[derive(Default)]
+pub struct ReplayFrame {
+    pub frame: u32,
+    /// Ids of the entities that died
+    pub deaths: Vec<u32>,
+    /// Entities born, taking the next ids
+    pub births: Vec<ReplayBirth>,
+    pub states: Vec<ReplayState>,
+}
+
+/// Replay file being recorded
+#[derive(Resource)]
+pub struct ReplayWriter {
+    writer: BufWriter<File>,
+}
+impl ReplayWriter {
+    pub fn create(path: &Path, header: &ReplayHeader) -> io::Result<Self> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        writer.write_all(MAGIC)?;
+        writer.write_all(&FORMAT_VERSION.to_le_bytes())?;
+        writer.write_all(&header.framerate.to_le_bytes())?;
+        writer.write_all(&header.seed.to_le_bytes())?;
+        write_string(&mut writer, &header.biome)?;
+        writer.write_all(&(header.species.len() as u16).to_le_bytes())?;
+        for species_key in &header.species {
+            write_string(&mut writer, species_key)?;
+        }
+        Ok(Self { writer })
+    }
+
+    pub fn write_frame(&mut self, frame: &ReplayFrame) -> io::Result<()> {
+        let writer = &mut self.writer;
+        writer.write_all(&frame.frame.to_le_bytes())?;
+        writer.write_all(&(frame.deaths.len() as u32).to_le_bytes())?;
+        for id in &frame.deaths {
+            writer.write_all(&id.to_le_bytes())?;
+        }
+        writer.write_all(&(frame.births.len() as u32).to_le_bytes())?;
+        for birth in &frame.births {
+            let mut species = birth.species;
+            if birth.carcass {
+                species |= CARCASS_FLAG;
+            }
+            if birth.seed {
+                species |= SEED_FLAG;
+            }
+            writer.write_all(&species.to_le_bytes())?;
+            writer.write_all(&birth.size.to_le_bytes())?;
+            writer.write_all(&birth.max_energy.to_le_bytes())?;
+            for channel in birth.color {
+                writer.write_all(&channel.to_le_bytes())?;
+            }
+        }
+        writer.write_all(&(frame.states.len() as u32).to_le_bytes())?;
+        for state in &frame.states {
+            let position = (state.position * POSITION_SCALE)
+                .round()
+                .clamp(Vec2::splat(i16::MIN as f32), Vec2::splat(i16::MAX as f32));
+            let rotation =
+                state.rotation.rem_euclid(std::f32::consts::TAU) / std::f32::consts::TAU * 65536.0;
+            let energy = state.energy_ratio.clamp(0.0, 1.0) * u16::MAX as f32;
+            writer.write_all(&(position.x as i16).to_le_bytes())?;
+            writer.write_all(&(position.y as i16).to_le_bytes())?;
+            writer.write_all(&(rotation as u32 as u16).to_le_bytes())?;
+            writer.write_all(&(energy.round() as u16).to_le_bytes())?;
+        }
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+/// Replay file being played
+pub struct ReplayReader {
+    reader: BufReader<File>,
+    pub header: ReplayHeader,
+}
+impl ReplayReader {
+    pub fn open(path: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut reader = BufReader::new(
+            File::open(path)
+                .map_err(|e| format!("Failed to open replay {}: {}", path.display(), e))?,
+        );
+        let mut magic = [0; 8];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(format!("{} is not a replay file", path.display()).into());
+        }
+        let version = u16::from_le_bytes(read_bytes(&mut reader)?);
+        if version != FORMAT_VERSION {
+            return Err(format!("Unsupported replay format version {}", version).into());
+        }
+        let framerate = f32::from_le_bytes(read_bytes(&mut reader)?);
+        let seed = u32::from_le_bytes(read_bytes(&mut reader)?);
+        let biome = read_string(&mut reader)?;
+        let nb_species = u16::from_le_bytes(read_bytes(&mut reader)?);
+        let species = (0..nb_species)
+            .map(|_| read_string(&mut reader))
+            .collect::<io::Result<_>>()?;
+
+        Ok(Self {
+            reader,
+            header: ReplayHeader {
+                framerate,
+                seed,
+                biome,
+                species,
+            },
+        })
+    }
+
+    /// Next frame, `None` at the end of the replay
+    pub fn read_frame(&mut self) -> io::Result<Option<ReplayFrame>> {
+        let reader = &mut self.reader;
+        let frame = match read_bytes(reader) {
+            Ok(bytes) => u32::from_le_bytes(bytes),
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+            Err(e) => return Err(e),
+        };
+
+        let nb_deaths = u32::from_le_bytes(read_bytes(reader)?);
+        let deaths = (0..nb_deaths)
+            .map(|_| read_bytes(reader).map(u32::from_le_bytes))
+            .collect::<io::Result<_>>()?;
+        let nb_births = u32::from_le_bytes(read_bytes(reader)?);
+        let births = (0..nb_births)
+            .map(|_| {
+                let species = u16::from_le_bytes(read_bytes(reader)?);
+                Ok(ReplayBirth {
+                    species: species & !(CARCASS_FLAG | SEED_FLAG),
+                    carcass: species & CARCASS_FLAG != 0,
+                    seed: species & SEED_FLAG != 0,
+                    size: f32::from_le_bytes(read_bytes(reader)?),
+                    max_energy: f32::from_le_bytes(read_bytes(reader)?),
+                    color: [
+                        f32::from_le_bytes(read_bytes(reader)?),
+                        f32::from_le_bytes(read_bytes(reader)?),
+                        f32::from_le_bytes(read_bytes(reader)?),
+                    ],
+                })
+            })
+            .collect::<io::Result<_>>()?;
+        let nb_states = u32::from_le_bytes(read_bytes(reader)?);
+        let states = (0..nb_states)
+            .map(|_| {
+                let x = i16::from_le_bytes(read_bytes(reader)?);
+                let y = i16::from_le_bytes(read_bytes(reader)?);
+                let rotation = u16::from_le_bytes(read_bytes(reader)?);
+                let energy = u16::from_le_bytes(read_bytes(reader)?);
+                Ok(ReplayState {
+                    position: Vec2::new(x as f32, y as f32) / POSITION_SCALE,
+                    rotation: rotation as f32 / 65536.0 * std::f32::consts::TAU,
+                    energy_ratio: energy as f32 / u16::MAX as f32,
+                })
+            })
+            .collect::<io::Result<_>>()?;
+
+        Ok(Some(ReplayFrame {
+            frame,
+            deaths,
+            births,
+            states,
+        }))
+    }
+}
+
+fn write_string(writer: &mut impl Write, text: &str) -> io::Result<()> {
+    writer.write_all(&(text.len() as u16).to_le_bytes())?;
+    writer.write_all(text.as_bytes())
+}
+
+fn read_string(reader: &mut impl Read) -> io::Result<String> {
+    let length = u16::from_le_bytes(read_bytes(reader)?);
+    let mut bytes = vec![0; length as usize];
+    reader.read_exact(&mut bytes)?;
+    String::from_utf8(bytes).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+fn read_bytes<const N: usize>(reader: &mut impl Read) -> io::Result<[u8; N]> {
+    let mut bytes = [0; N];
+    reader.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn replays_read_back_what_was_written() {
+        let path = std::env::temp_dir().join("teemlabs_replay_round_trip.bin");
+        let header = ReplayHeader {
+            framerate: 30.0,
+            seed: 42,
+            biome: "irr_umar".to_string(),
+            species: vec!["dravym".to_string(), "vyrmosa".to_string()],
+        };
+        let frames = [
+            ReplayFrame {
+                frame: 0,
+                deaths: Vec::new(),
+                births: vec![ReplayBirth {
+                    species: 1,
+                    carcass: false,
+                    seed: false,
+                    size: 4.0,
+                    max_energy: 200.0,
+                    color: [0.1, 0.2, 0.3],
+                }],
+                states: vec![ReplayState {
+                    position: Vec2::new(-12.5, 300.25),
+                    rotation: 1.0,
+                    energy_ratio: 0.5,
+                }],
+            },
+            ReplayFrame {
+                frame: 1,
+                deaths: vec![0],
+                births: vec![
+                    ReplayBirth {
+                        species: 1,
+                        carcass: true,
+                        seed: false,
+                        size: 4.0,
+                        max_energy: 100.0,
+                        color: [0.4, 0.4, 0.4],
+                    },
+                    ReplayBirth {
+                        species: 1,
+                        carcass: false,
+                        seed: true,
+                        size: 1.0,
+                        max_energy: 50.0,
+                        color: [0.0, 0.8, 0.2],
+                    },
+                ],
+                states: vec![
+                    ReplayState {
+                        position: Vec2::new(-12.5, 301.0),
+                        rotation: -1.0,
+                        energy_ratio: 1.0,
+                    },
+                    ReplayState {
+                        position: Vec2::new(20.0, -4.0),
+                        rotation: 0.0,
+                        energy_ratio: 0.25,
+                    },
+                ],
+            },
+        ];
+        let mut writer = ReplayWriter::create(&path, &header).unwrap();
+        for frame in &frames {
+            writer.write_frame(frame).unwrap();
+        }
+        writer.flush().unwrap();
+
+        let mut reader = ReplayReader::open(&path).unwrap();
+        assert_eq!(reader.header.framerate, 30.0);
+        assert_eq!(reader.header.seed, 42);
+        assert_eq!(reader.header.biome, "irr_umar");
+        assert_eq!(reader.header.species, header.species);
+        for expected in &frames {
+            let frame = reader.read_frame().unwrap().unwrap();
+            assert_eq!(frame.frame, expected.frame);
+            assert_eq!(frame.deaths, expected.deaths);
+            assert_eq!(frame.births.len(), expected.births.len());
+            for (birth, expected) in frame.births.iter().zip(&expected.births) {
+                assert_eq!(birth.species, expected.species);
+                assert_eq!(birth.carcass, expected.carcass);
+                assert_eq!(birth.seed, expected.seed);
+                assert_eq!(birth.size, expected.size);
+                assert_eq!(birth.max_energy, expected.max_energy);
+                assert_eq!(birth.color, expected.color);
+            }
+            assert_eq!(frame.states.len(), expected.states.len());
+            for (state, expected) in frame.states.iter().zip(&expected.states) {
+                assert_eq!(state.position, expected.position);
+                let rotation = expected.rotation.rem_euclid(std::f32::consts::TAU);
+                assert!((state.rotation - rotation).abs() < 1e-3);
+                assert!((state.energy_ratio - expected.energy_ratio).abs() < 1e-4);
+            }
+        }
+        assert!(reader.read_frame().unwrap().is_none());
+    }
+
+    #[test]
+    fn replays_reject_other_files() {
+        let path = std::env::temp_dir().join("teemlabs_replay_invalid.bin");
+        std::fs::write(&path, b"NOTAREPLAYFILE").unwrap();
+        assert!(ReplayReader::open(&path).is_err());
+    }
+}
@@ -1,4 +1,15 @@
+use crate::components::{
+    Age, BehaviourState, Brain, GameConfig, Gene, Genome, Network, Species, SpeciesRegistry,
+};
+use crate::config::{DecisionModel, NETWORK_EXPORTS};
 use bevy::prelude::*;
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
 
 /// Resources
 #[derive(Resource)]
@@ -6,3 +17,683 @@ pub struct SimulationMetadata {
     pub path_dir: String,
     pub name: String,
 }
+
+/// Run settings resolved from the command line, the simulation config and defaults
+#[derive(Resource, Clone, Serialize)]
+pub struct RunSettings {
+    /// 32 bits, as TOML integers are signed 64-bit and cannot record every u64
+    pub seed: u32,
+    pub duration: f32,
+    pub framerate: f32,
+    pub output_dir: PathBuf,
+    pub name: Option<String>,
+    /// Simulated times in seconds at which world snapshots are saved
+    pub snapshot_at: Vec<f32>,
+    /// Snapshot the run started from
+    pub restored_from: Option<PathBuf>,
+    /// Record a replay of the run
+    pub replay: bool,
+}
+impl RunSettings {
+    pub fn time_step(&self) -> f32 {
+        1.0 / self.framerate
+    }
+
+    pub fn max_frames(&self) -> u32 {
+        (self.duration * self.framerate) as u32
+    }
+}
+
+/// Independent random streams, one per consumer, so that adding draws to one system
+/// does not shift the sequence seen by the others
+#[derive(Clone, Copy)]
+pub enum RngStream {
+    Spawn,
+    Idle,
+    Regeneration,
+    Reproduction,
+    Mating,
+    Dispersal,
+    Behaviour,
+}
+impl RngStream {
+    pub const COUNT: usize = 7;
+}
+
+/// Seeded simulation random number generator
+#[derive(Resource)]
+pub struct SimulationRng {
+    streams: Vec<ChaCha8Rng>,
+}
+impl SimulationRng {
+    pub fn new(seed: u32) -> Self {
+        let streams = (0..RngStream::COUNT as u64)
+            .map(|stream| {
+                let mut rng = ChaCha8Rng::seed_from_u64(seed as u64);
+                rng.set_stream(stream);
+                rng
+            })
+            .collect();
+        Self { streams }
+    }
+
+    /// Streams restored to the given number of consumed words. Streams added since the
+    /// positions were saved start from the beginning
+    pub fn restore(seed: u32, positions: &[u64]) -> Result<Self, String> {
+        if positions.len() > RngStream::COUNT {
+            return Err(format!(
+                "Expected at most {} random stream positions, got {}",
+                RngStream::COUNT,
+                positions.len()
+            ));
+        }
+        let mut rng = Self::new(seed);
+        for (stream, position) in rng.streams.iter_mut().zip(positions) {
+            stream.set_word_pos(*position as u128);
+        }
+        Ok(rng)
+    }
+
+    pub fn stream(&mut self, stream: RngStream) -> &mut ChaCha8Rng {
+        &mut self.streams[stream as usize]
+    }
+
+    /// Number of words consumed by each stream
+    pub fn positions(&self) -> Vec<u64> {
+        self.streams
+            .iter()
+            .map(|stream| stream.get_word_pos() as u64)
+            .collect()
+    }
+}
+
+/// Desired forces of the organisms with an external brain, consumed each frame. This is
+/// the hook of applications embedding the simulation, which must set them before the
+/// fixed update; the command line never does, so its runs reject external brains
+#[derive(Resource, Default)]
+pub struct ExternalIntents(pub HashMap<Entity, Vec2>);
+
+/// Nutrients over the water area, on a grid of square cells
+#[derive(Resource)]
+pub struct NutrientField {
+    origin: Vec2,
+    cell_size: f32,
+    columns: usize,
+    rows: usize,
+    values: Vec<f32>,
+}
+impl NutrientField {
+    /// Field covering `size` from its bottom left `origin`, each cell starting with
+    /// `initial` nutrients
+    pub fn new(origin: Vec2, size: Vec2, cell_size: f32, initial: f32) -> Self {
+        let columns = (size.x / cell_size).ceil().max(1.0) as usize;
+        let rows = (size.y / cell_size).ceil().max(1.0) as usize;
+        Self {
+            origin,
+            cell_size,
+            columns,
+            rows,
+            values: vec![initial; columns * rows],
+        }
+    }
+
+    /// Index of the cell containing a position, positions outside the field falling in
+    /// the nearest cell
+    fn cell(&self, position: Vec2) -> usize {
+        let local = (position - self.origin) / self.cell_size;
+        let column = (local.x.max(0.0) as usize).min(self.columns - 1);
+        let row = (local.y.max(0.0) as usize).min(self.rows - 1);
+        row * self.columns + column
+    }
+
+    pub fn deposit(&mut self, position: Vec2, amount: f32) {
+        let cell = self.cell(position);
+        self.values[cell] += amount;
+    }
+
+    /// Take up to `amount` nutrients from the cell at a position, returning the amount taken
+    pub fn take(&mut self, position: Vec2, amount: f32) -> f32 {
+        let cell = self.cell(position);
+        let taken = amount.min(self.values[cell]);
+        self.values[cell] -= taken;
+        taken
+    }
+
+    /// Exchange a share of the difference with each neighbouring cell, keeping the total
+    pub fn diffuse(&mut self, share: f32) {
+        let share = share.clamp(0.0, 0.25);
+        let previous = self.values.clone();
+        for row in 0..self.rows {
+            for column in 0..self.columns {
+                let cell = row * self.columns + column;
+                let neighbours = [
+                    (column > 0).then(|| cell - 1),
+                    (column + 1 < self.columns).then(|| cell + 1),
+                    (row > 0).then(|| cell - self.columns),
+                    (row + 1 < self.rows).then(|| cell + self.columns),
+                ];
+                for neighbour in neighbours.into_iter().flatten() {
+                    self.values[cell] += share * (previous[neighbour] - previous[cell]);
+                }
+            }
+        }
+    }
+
+    pub fn values(&self) -> &[f32] {
+        &self.values
+    }
+
+    /// Restore the values saved from a field of the same dimensions
+    pub fn restore(&mut self, values: Vec<f32>) -> Result<(), String> {
+        if values.len() != self.values.len() {
+            return Err(format!(
+                "Expected {} nutrient cells, got {}",
+                self.values.len(),
+                values.len()
+            ));
+        }
+        self.values = values;
+        Ok(())
+    }
+}
+
+/// Layout of world snapshots, bumped when it changes
+pub const SNAPSHOT_VERSION: u32 = 1;
+
+/// Full simulation state at a given frame, from which a new run can start
+#[derive(Resource, Serialize, Deserialize)]
+pub struct WorldSnapshot {
+    /// Layout of the file, see `SNAPSHOT_VERSION`
+    pub version: u32,
+    pub biome: String,
+    pub frame: u32,
+    pub framerate: f32,
+    pub seed: u32,
+    /// Words consumed by each random stream
+    pub rng_positions: Vec<u64>,
+    pub entities: Vec<EntitySnapshot>,
+    /// Seeds drifting or carried, in the same order as the entities
+    #[serde(default)]
+    pub seeds: Vec<SeedSnapshot>,
+    /// Nutrient cells, row by row from the bottom left, when the field is enabled
+    #[serde(default)]
+    pub nutrients: Option<Vec<f32>>,
+}
+impl WorldSnapshot {
+    pub fn load(path: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read snapshot {}: {}", path.display(), e))?;
+        let table: toml::Table = toml::from_str(&content)
+            .map_err(|e| format!("Failed to parse snapshot {}: {}", path.display(), e))?;
+
+        // Check the layout first, older snapshots would fail to parse with unclear errors
+        let version = table
+            .get("version")
+            .map_or_else(|| "none".to_string(), ToString::to_string);
+        if version != SNAPSHOT_VERSION.to_string() {
+            return Err(format!(
+                "Unsupported snapshot version {} in {}, expected {}",
+                version,
+                path.display(),
+                SNAPSHOT_VERSION
+            )
+            .into());
+        }
+        table
+            .try_into()
+            .map_err(|e| format!("Failed to parse snapshot {}: {}", path.display(), e).into())
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct EntitySnapshot {
+    pub species: String,
+    pub energy: f32,
+    pub max_energy: f32,
+    pub size: f32,
+    pub speed: f32,
+    pub position: [f32; 2],
+    /// Rotation around the z axis in radians
+    pub rotation: f32,
+    pub linear_velocity: [f32; 2],
+    pub angular_velocity: f32,
+    #[serde(default)]
+    pub hunts: Option<Vec<String>>,
+    #[serde(default)]
+    pub vision: Option<VisionSnapshot>,
+    #[serde(default)]
+    pub photosynthesis: Option<f32>,
+    #[serde(default)]
+    pub genome: Option<Genome>,
+    #[serde(default)]
+    pub mating_type: Option<u8>,
+    /// Seconds since birth
+    #[serde(default)]
+    pub age: f32,
+    /// Weights of a neural brain
+    #[serde(default)]
+    pub network: Option<Network>,
+    /// State of a behaviour brain
+    #[serde(default)]
+    pub behaviour: Option<BehaviourSnapshot>,
+    /// Set for the carcass of an organism of the species
+    #[serde(default)]
+    pub carcass: Option<CarcassSnapshot>,
+}
+
+/// Networks of the oldest organisms of each neural species at the end of a run, from
+/// which the founders of a new run can start
+#[derive(Serialize, Deserialize)]
+pub struct NetworkArchive {
+    pub networks: Vec<ArchivedNetwork>,
+}
+impl NetworkArchive {
+    pub fn load(path: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read networks {}: {}", path.display(), e))?;
+        toml::from_str(&content)
+            .map_err(|e| format!("Failed to parse networks {}: {}", path.display(), e).into())
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ArchivedNetwork {
+    pub species: String,
+    /// Seconds the organism survived
+    pub age: f32,
+    pub network: Network,
+}
+
+/// Export the networks of the oldest organisms of each neural species when the run stops,
+/// so that a later run can start from them. The archive is empty when they all died out
+pub fn export_networks(
+    config: Res<GameConfig>,
+    species_registry: Res<SpeciesRegistry>,
+    simulation_metadata: Res<SimulationMetadata>,
+    stop_state: Res<StopState>,
+    organisms: Query<(&Species, &Brain, &Age)>,
+) {
+    if stop_state.reason.is_none() || !stop_state.is_changed() {
+        return;
+    }
+    let runtime = &config.runtime;
+    if !runtime.lore.biomes[&runtime.simulation.biome]
+        .species
+        .values()
+        .any(|species_data| species_data.brain == Some(DecisionModel::Neural))
+    {
+        return;
+    }
+
+    let mut survivors: Vec<_> = organisms
+        .iter()
+        .filter_map(|(species, brain, age)| Some((*species, brain.network()?, age.0)))
+        .collect();
+    if survivors.is_empty() {
+        println!("No neural organism left, exporting no network");
+    }
+    survivors.sort_by(|(species1, _, age1), (species2, _, age2)| {
+        species1
+            .index()
+            .cmp(&species2.index())
+            .then(age2.total_cmp(age1))
+    });
+
+    let mut networks = Vec::new();
+    for (species, network, age) in survivors {
+        let key = species_registry.key(species);
+        if networks
+            .iter()
+            .filter(|archived: &&ArchivedNetwork| archived.species == key)
+            .count()
+            < NETWORK_EXPORTS
+        {
+            networks.push(ArchivedNetwork {
+                species: key.to_string(),
+                age,
+                network: network.clone(),
+            });
+        }
+    }
+    let networks_path = format!("{}/networks.toml", simulation_metadata.path_dir);
+    if let Err(e) = std::fs::write(
+        &networks_path,
+        toml::to_string(&NetworkArchive { networks }).expect("Failed to serialize networks."),
+    ) {
+        eprintln!("Failed to write {}: {}", networks_path, e);
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct CarcassSnapshot {
+    /// Linear RGB colour
+    pub color: [f32; 3],
+}
+
+/// Seed and the plant it carries
+#[derive(Serialize, Deserialize)]
+pub struct SeedSnapshot {
+    pub species: String,
+    pub position: [f32; 2],
+    pub velocity: [f32; 2],
+    /// Seconds left drifting before germination
+    pub drift_time: f32,
+    pub energy: f32,
+    pub max_energy: f32,
+    pub genome: Genome,
+    #[serde(default)]
+    pub mating_type: Option<u8>,
+    #[serde(default)]
+    pub photosynthesis: Option<f32>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct BehaviourSnapshot {
+    pub state: BehaviourState,
+    /// Seconds since the state was entered, or since the last heading change when
+    /// wandering
+    pub elapsed: f32,
+    pub heading: [f32; 2],
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct VisionSnapshot {
+    pub detection_range: f32,
+    pub nb_rays: u32,
+    /// Field of view in radians
+    pub field_of_view: f32,
+}
+
+/// Reason a run stopped
+#[derive(Debug, Clone)]
+pub enum StopReason {
+    MaxDuration,
+    Extinction(String),
+    PopulationThreshold(String, u32),
+    PopulationBelow(u32),
+    Equilibrium,
+    EndOfReplay,
+}
+impl std::fmt::Display for StopReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StopReason::MaxDuration => write!(f, "Maximum duration reached"),
+            StopReason::Extinction(species) => write!(f, "Extinction of {}", species),
+            StopReason::PopulationThreshold(species, population) => {
+                write!(f, "Population of {} reached {}", species, population)
+            }
+            StopReason::PopulationBelow(population) => {
+                write!(f, "Total population fell below {}", population)
+            }
+            StopReason::Equilibrium => write!(f, "Ecosystem equilibrium detected"),
+            StopReason::EndOfReplay => write!(f, "End of replay"),
+        }
+    }
+}
+
+/// Stop report written to the simulation folder
+#[derive(Serialize, Deserialize)]
+pub struct StopReport {
+    pub reason: String,
+    pub frame: u32,
+    pub time: f32,
+    pub populations: BTreeMap<String, u32>,
+}
+
+/// Set once a stop condition is met
+#[derive(Resource, Default)]
+pub struct StopState {
+    pub reason: Option<StopReason>,
+}
+
+/// Per-frame population and energy statistics, written as CSV
+#[derive(Resource)]
+pub struct StatisticsWriter {
+    writer: BufWriter<File>,
+}
+impl StatisticsWriter {
+    pub fn create(path: &Path, species_registry: &SpeciesRegistry) -> std::io::Result<Self> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        write!(writer, "frame,time")?;
+        for species in species_registry.iter() {
+            let key = species_registry.key(species);
+            write!(writer, ",{}_population,{}_mean_energy", key, key)?;
+        }
+        writeln!(writer)?;
+        Ok(Self { writer })
+    }
+
+    pub fn write_row(&mut self, frame: u32, time: f32, rows: &[(u32, f32)]) -> std::io::Result<()> {
+        write!(self.writer, "{},{}", frame, time)?;
+        for (population, mean_energy) in rows {
+            write!(self.writer, ",{},{}", population, mean_energy)?;
+        }
+        writeln!(self.writer)
+    }
+
+    pub fn flush(&mut self) -> std::io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+/// Mean genome of each species, written as CSV once per simulated second
+#[derive(Resource)]
+pub struct TraitsWriter {
+    writer: BufWriter<File>,
+}
+impl TraitsWriter {
+    pub fn create(path: &Path) -> std::io::Result<Self> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        write!(writer, "frame,time,species,population")?;
+        for gene in Gene::ALL {
+            write!(writer, ",{}", gene.key())?;
+        }
+        writeln!(writer)?;
+        Ok(Self { writer })
+    }
+
+    pub fn write_row(
+        &mut self,
+        frame: u32,
+        time: f32,
+        species_key: &str,
+        population: u32,
+        means: &[f32],
+    ) -> std::io::Result<()> {
+        write!(
+            self.writer,
+            "{},{},{},{}",
+            frame, time, species_key, population
+        )?;
+        for mean in means {
+            write!(self.writer, ",{}", mean)?;
+        }
+        writeln!(self.writer)
+    }
+
+    pub fn flush(&mut self) -> std::io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::Rng;
+
+    fn draws(rng: &mut SimulationRng, stream: RngStream) -> Vec<u32> {
+        (0..8).map(|_| rng.stream(stream).random()).collect()
+    }
+
+    const SNAPSHOT: &str = r#"
+version = 1
+biome = "irr_umar"
+frame = 60
+framerate = 30.0
+seed = 7
+rng_positions = [16, 0, 48, 8]
+
+[[entities]]
+species = "dravym"
+energy = 120.5
+max_energy = 200.0
+size = 4.0
+speed = 38.0
+position = [-12.5, 40.0]
+rotation = 1.5
+linear_velocity = [3.0, -1.0]
+angular_velocity = 0.25
+hunts = ["vyrmosa"]
+
+[entities.behaviour]
+state = "flee"
+elapsed = 0.5
+heading = [0.0, -1.0]
+
+[entities.vision]
+detection_range = 50.0
+nb_rays = 24
+field_of_view = 2.0
+
+[[entities]]
+species = "vyrmosa"
+energy = 30.0
+max_energy = 50.0
+size = 1.0
+speed = 0.0
+position = [100.0, -20.0]
+rotation = 0.0
+linear_velocity = [0.0, 0.0]
+angular_velocity = 0.0
+photosynthesis = 2.5
+
+[[seeds]]
+species = "vyrmosa"
+position = [90.0, -25.0]
+velocity = [-0.5, 0.25]
+drift_time = 1.5
+energy = 20.0
+max_energy = 50.0
+photosynthesis = 2.5
+
+[seeds.genome]
+speed = 0.0
+size = 1.0
+detection_range = 60.0
+field_of_view = 6.25
+boldness = 6.0
+fearfulness = 18.0
+sociability = 0.0
+reproduction_threshold = 0.8
+color = [0.25, 0.75, 0.25]
+"#;
+
+    #[test]
+    fn snapshots_read_back_what_was_saved() {
+        let snapshot: WorldSnapshot = toml::from_str(SNAPSHOT).unwrap();
+        let saved = toml::to_string(&snapshot).unwrap();
+        let path = std::env::temp_dir().join("teemlabs_snapshot_round_trip.toml");
+        std::fs::write(&path, &saved).unwrap();
+        let restored = WorldSnapshot::load(&path).unwrap();
+        assert_eq!(toml::to_string(&restored).unwrap(), saved);
+        assert_eq!(restored.entities.len(), 2);
+        assert_eq!(restored.entities[0].position, [-12.5, 40.0]);
+        assert_eq!(restored.entities[1].photosynthesis, Some(2.5));
+        assert_eq!(restored.seeds.len(), 1);
+        assert_eq!(restored.seeds[0].drift_time, 1.5);
+        let behaviour = restored.entities[0].behaviour.as_ref().unwrap();
+        assert_eq!(behaviour.state, BehaviourState::Flee);
+        assert_eq!(behaviour.heading, [0.0, -1.0]);
+    }
+
+    #[test]
+    fn snapshots_of_other_versions_are_rejected() {
+        let path = std::env::temp_dir().join("teemlabs_snapshot_version.toml");
+        std::fs::write(
+            &path,
+            SNAPSHOT.replace("version = 1", "version = \"0.4.2\""),
+        )
+        .unwrap();
+        let error = WorldSnapshot::load(&path).err().unwrap().to_string();
+        assert!(error.contains("Unsupported snapshot version"), "{}", error);
+    }
+
+    #[test]
+    fn restored_streams_continue_where_they_stopped() {
+        let mut rng = SimulationRng::new(7);
+        draws(&mut rng, RngStream::Spawn);
+        draws(&mut rng, RngStream::Idle);
+        draws(&mut rng, RngStream::Idle);
+        let mut restored = SimulationRng::restore(7, &rng.positions()).unwrap();
+        for stream in [RngStream::Spawn, RngStream::Idle, RngStream::Regeneration] {
+            assert_eq!(draws(&mut rng, stream), draws(&mut restored, stream));
+        }
+        // Streams added since a snapshot start from the beginning
+        assert!(SimulationRng::restore(7, &[0]).is_ok());
+        assert!(SimulationRng::restore(7, &[0; RngStream::COUNT + 1]).is_err());
+    }
+
+    #[test]
+    fn same_seeds_draw_the_same_numbers() {
+        let (mut rng1, mut rng2) = (SimulationRng::new(7), SimulationRng::new(7));
+        assert_eq!(
+            draws(&mut rng1, RngStream::Spawn),
+            draws(&mut rng2, RngStream::Spawn)
+        );
+        assert_ne!(
+            draws(&mut SimulationRng::new(7), RngStream::Spawn),
+            draws(&mut SimulationRng::new(8), RngStream::Spawn)
+        );
+    }
+
+    #[test]
+    fn streams_do_not_disturb_each_other() {
+        let mut rng1 = SimulationRng::new(7);
+        let mut rng2 = SimulationRng::new(7);
+        // Extra draws on one stream leave the others unchanged
+        draws(&mut rng1, RngStream::Idle);
+        assert_eq!(
+            draws(&mut rng1, RngStream::Regeneration),
+            draws(&mut rng2, RngStream::Regeneration)
+        );
+        assert_ne!(
+            draws(&mut rng2, RngStream::Spawn),
+            draws(&mut rng2, RngStream::Idle)
+        );
+    }
+
+    /// Field of 4 by 3 cells of 10 pixels, starting empty
+    fn field() -> NutrientField {
+        NutrientField::new(Vec2::ZERO, Vec2::new(40.0, 30.0), 10.0, 0.0)
+    }
+
+    #[test]
+    fn diffusion_keeps_the_total() {
+        let mut field = field();
+        field.deposit(Vec2::new(5.0, 5.0), 100.0);
+        field.deposit(Vec2::new(25.0, 15.0), 40.0);
+        for share in [0.1, 0.25, 1.0] {
+            for _ in 0..50 {
+                field.diffuse(share);
+                assert!((field.values().iter().sum::<f32>() - 140.0).abs() < 1e-3);
+                assert!(field.values().iter().all(|value| *value >= 0.0));
+            }
+        }
+        // Long diffusion evens the field out
+        assert!(
+            field
+                .values()
+                .iter()
+                .all(|value| (value - 140.0 / 12.0).abs() < 0.1)
+        );
+    }
+
+    #[test]
+    fn positions_outside_fall_in_the_nearest_cell() {
+        let mut field = field();
+        field.deposit(Vec2::new(-50.0, 100.0), 1.0);
+        assert_eq!(field.values()[8], 1.0);
+        assert_eq!(field.take(Vec2::new(5.0, 25.0), 3.0), 1.0);
+        assert_eq!(field.values().iter().sum::<f32>(), 0.0);
+    }
+}
@@ -1,5 +1,13 @@
 mod rendering;
+mod replay;
 mod simulation;
+mod snapshot;
+mod statistics;
+mod stop;
 
 pub use rendering::*;
+pub use replay::*;
 pub use simulation::*;
+pub use snapshot::*;
+pub use statistics::*;
+pub use stop::*;
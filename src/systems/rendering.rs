@@ -7,6 +7,36 @@ use crate::components::*;
 use crate::config::*;
 use crate::resources::*;
 
+/// Entities
+pub fn attach_entity_visuals(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    entities: Query<(Entity, &EntityColor, &Size), Added<EntityColor>>,
+) {
+    for (entity, color, size) in &entities {
+        commands.entity(entity).try_insert((
+            Mesh2d(meshes.add(Circle::new(size.value()))),
+            MeshMaterial2d(materials.add(color.value())),
+        ));
+    }
+}
+
+/// Basin and obstacles, drawn from their shape
+pub fn attach_terrain_visuals(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    terrain: Query<(Entity, &Terrain, &EntityColor), Added<Terrain>>,
+) {
+    for (entity, shape, color) in &terrain {
+        commands.entity(entity).try_insert((
+            Mesh2d(meshes.add(shape.mesh())),
+            MeshMaterial2d(materials.add(color.value())),
+        ));
+    }
+}
+
 /// HUD
 pub fn update_hud(
     frame_count: Res<FrameCount>,
@@ -61,10 +91,13 @@ pub fn update_debugger(
     }
 }
 
+/// Rays and hit points drawn on the previous frame
+type VisualizationFilter = Or<(With<RaycastVisualization>, With<HitPointVisualization>)>;
+
 pub fn visualize_raycast(
     mut commands: Commands,
     ray_query: Query<(&VisionResults, Option<&Hunter>, &Species)>,
-    old_viz: Query<Entity, Or<(With<RaycastVisualization>, With<HitPointVisualization>)>>,
+    old_viz: Query<Entity, VisualizationFilter>,
     entity_query: Query<(&Species, Option<&Hunter>)>,
 ) {
     // Clean up old visualizations
@@ -158,31 +191,30 @@ pub fn capture_frame(
     mut app_exit: MessageWriter<AppExit>,
     mut capture_q: Query<&mut Capture>,
     mut frame_counter: Local<u32>,
-    mut stop_requested: Local<bool>,
     mut stop_requested_at: Local<Option<Instant>>,
     simulation_metadata: Res<SimulationMetadata>,
+    settings: Res<RunSettings>,
+    stop_state: Res<StopState>,
 ) {
     let mut capture = capture_q.single_mut().unwrap();
 
-    if !capture.is_capturing() && !*stop_requested {
+    if !capture.is_capturing() && stop_requested_at.is_none() {
         capture.start(
             mp4_ffmpeg_cli_pipe::Mp4FfmpegCliPipeEncoder::new(format!(
                 "{}/{}.mp4",
                 simulation_metadata.path_dir, simulation_metadata.name
             ))
             .expect("Failed to create MP4 encoder")
-            .with_framerate(30)
+            .with_framerate(settings.framerate as u32)
             .with_crf(18)
             .with_preset("p7".to_string()),
         );
     }
 
     *frame_counter += 1;
-    println!("{}", *frame_counter);
 
-    // When we reach the frame limit: request a stop (don't exit yet).
-    if *frame_counter >= MAX_FRAMES_TO_CAPTURE && !*stop_requested {
-        *stop_requested = true;
+    // When a stop condition is met: request a stop (don't exit yet).
+    if stop_state.reason.is_some() && stop_requested_at.is_none() {
         *stop_requested_at = Some(Instant::now());
         // Prefer calling the API stop() if available:
         capture.stop(); // if Capture exposes stop(); otherwise see note below.
@@ -193,7 +225,7 @@ pub fn capture_frame(
     }
 
     // If stop was requested, wait for capture to end (or timeout).
-    if *stop_requested {
+    if let Some(started) = *stop_requested_at {
         // If capture finished cleanly -> exit
         if !capture.is_capturing() {
             println!("Capture finished — exiting.");
@@ -202,16 +234,13 @@ pub fn capture_frame(
         }
 
         // Safety: force exit if encoder never finishes within a reasonable wall-clock time.
-        if let Some(started) = *stop_requested_at
-            && started.elapsed().as_secs() > 60
-        {
+        if started.elapsed().as_secs() > 60 {
             eprintln!("Capture did not finish within 60s — forcing exit.");
             app_exit.write(AppExit::Success);
         }
     }
 }
 
-pub fn manual_physics_step(mut physics_time: ResMut<Time<Physics>>) {
-    println!("Physics step advancing.");
-    physics_time.advance_by(std::time::Duration::from_secs_f32(FIXED_TIME_STEP));
+pub fn manual_physics_step(settings: Res<RunSettings>, mut physics_time: ResMut<Time<Physics>>) {
+    physics_time.advance_by(std::time::Duration::from_secs_f32(settings.time_step()));
 }
@@ -0,0 +1,200 @@
+use bevy::{diagnostic::FrameCount, ecs::query::QueryData, prelude::*};
+use std::collections::HashMap;
+
+use crate::components::*;
+use crate::config::*;
+use crate::replay::*;
+use crate::resources::*;
+use crate::systems::Seed;
+
+/// Entities recorded so far, in birth order
+#[derive(Default)]
+pub struct ReplayTracker {
+    ids: HashMap<Entity, u32>,
+    alive: Vec<Entity>,
+    next_id: u32,
+}
+
+/// Components recorded in a replay, of organisms, carcasses and seeds
+#[derive(QueryData)]
+pub struct ReplayData {
+    entity: Entity,
+    species: Option<&'static Species>,
+    carcass: Option<&'static Carcass>,
+    seed: Option<&'static Seed>,
+    color: &'static EntityColor,
+    size: &'static Size,
+    energy: Option<&'static Energy>,
+    transform: &'static Transform,
+}
+impl ReplayDataItem<'_, '_> {
+    /// Energy of the organism or carcass, or of the plant carried by the seed
+    fn energy(&self) -> &Energy {
+        self.energy
+            .or(self.seed.map(Seed::energy))
+            .expect("Replayed entities have an energy or are seeds")
+    }
+}
+
+/// Entities recorded in a replay
+type Replayed = Or<(With<Species>, With<Carcass>, With<Seed>)>;
+
+/// Append the births, deaths and entity states of this frame to the replay
+pub fn record_replay(
+    frame_count: Res<FrameCount>,
+    stop_state: Res<StopState>,
+    mut replay: ResMut<ReplayWriter>,
+    mut tracker: Local<ReplayTracker>,
+    entities: Query<ReplayData, Replayed>,
+) {
+    // Nothing more to record once stopped
+    if stop_state.reason.is_some() && !stop_state.is_changed() {
+        return;
+    }
+
+    let mut frame = ReplayFrame {
+        frame: frame_count.0 + 1,
+        ..default()
+    };
+
+    // Deaths
+    let tracker = &mut *tracker;
+    tracker.alive.retain(|entity| {
+        if entities.contains(*entity) {
+            return true;
+        }
+        frame.deaths.push(tracker.ids[entity]);
+        tracker.ids.remove(entity);
+        false
+    });
+
+    // Births, in spawn order
+    let mut births: Vec<_> = entities
+        .iter()
+        .filter(|data| !tracker.ids.contains_key(&data.entity))
+        .collect();
+    births.sort_by_key(|data| data.entity.index());
+    for data in births {
+        let species = data
+            .species
+            .copied()
+            .or(data.carcass.map(|carcass| carcass.species))
+            .or(data.seed.map(Seed::species))
+            .expect("Replayed entities have a species or are carcasses or seeds");
+        let color = data.color.value().to_linear();
+        frame.births.push(ReplayBirth {
+            species: species.index() as u16,
+            carcass: data.carcass.is_some(),
+            seed: data.seed.is_some(),
+            size: data.size.value(),
+            max_energy: data.energy().max,
+            color: [color.red, color.green, color.blue],
+        });
+        tracker.ids.insert(data.entity, tracker.next_id);
+        tracker.alive.push(data.entity);
+        tracker.next_id += 1;
+    }
+
+    // States
+    for entity in &tracker.alive {
+        if let Ok(data) = entities.get(*entity) {
+            frame.states.push(ReplayState {
+                position: data.transform.translation.truncate(),
+                rotation: data.transform.rotation.to_euler(EulerRot::XYZ).2,
+                energy_ratio: data.energy().value() / data.energy().max,
+            });
+        }
+    }
+
+    let mut result = replay.write_frame(&frame);
+    if stop_state.reason.is_some() {
+        result = result.and_then(|_| replay.flush());
+    }
+    if let Err(e) = result {
+        eprintln!("Failed to write replay: {}", e);
+    }
+}
+
+/// Replay being played, with the entities spawned for its ids
+#[derive(Resource)]
+pub struct ReplayPlayback {
+    pub reader: ReplayReader,
+    /// Species of each species key of the replay header
+    pub species: Vec<Species>,
+    pub entities: HashMap<u32, Entity>,
+    pub alive: Vec<u32>,
+    pub next_id: u32,
+}
+
+/// Spawn, move and despawn entities from the next frame of the replay
+pub fn play_replay(
+    mut commands: Commands,
+    mut frame_count: ResMut<FrameCount>,
+    mut playback: ResMut<ReplayPlayback>,
+    mut stop_state: ResMut<StopState>,
+    mut entities: Query<(&mut Transform, &mut Energy)>,
+) {
+    if stop_state.reason.is_some() {
+        return;
+    }
+    let frame = match playback.reader.read_frame() {
+        Ok(Some(frame)) => frame,
+        Ok(None) => {
+            stop_state.reason = Some(StopReason::EndOfReplay);
+            return;
+        }
+        Err(e) => {
+            eprintln!("Failed to read replay: {}", e);
+            stop_state.reason = Some(StopReason::EndOfReplay);
+            return;
+        }
+    };
+    // Show the frame numbers of the recorded run
+    frame_count.0 = frame.frame.saturating_sub(1);
+
+    let playback = &mut *playback;
+    for id in &frame.deaths {
+        if let Some(entity) = playback.entities.remove(id) {
+            commands.entity(entity).despawn();
+        }
+    }
+    playback.alive.retain(|id| !frame.deaths.contains(id));
+
+    // Newborns are spawned with their first state below
+    let mut births = HashMap::new();
+    for birth in frame.births {
+        births.insert(playback.next_id, birth);
+        playback.alive.push(playback.next_id);
+        playback.next_id += 1;
+    }
+
+    for (id, state) in playback.alive.iter().zip(&frame.states) {
+        let transform = Transform::from_translation(state.position.extend(Z_ENTITIES))
+            .with_rotation(Quat::from_rotation_z(state.rotation));
+        if let Some(birth) = births.remove(id) {
+            let species = playback.species[birth.species as usize];
+            let mut entity_commands = commands.spawn((
+                EntityColor::new(birth.color[0], birth.color[1], birth.color[2]),
+                Size::new(birth.size),
+                Energy::new(birth.max_energy * state.energy_ratio, birth.max_energy),
+            ));
+            if birth.carcass {
+                entity_commands.insert((
+                    Carcass { species },
+                    transform.with_translation(state.position.extend(Z_CARCASSES)),
+                ));
+            } else if birth.seed {
+                entity_commands.insert(transform);
+            } else {
+                entity_commands.insert((species, transform));
+            }
+            playback.entities.insert(*id, entity_commands.id());
+        } else if let Some(entity) = playback.entities.get(id)
+            && let Ok((mut entity_transform, mut energy)) = entities.get_mut(*entity)
+        {
+            entity_transform.translation = state.position.extend(entity_transform.translation.z);
+            entity_transform.rotation = transform.rotation;
+            energy.current = energy.max * state.energy_ratio;
+        }
+    }
+}
@@ -1,28 +1,70 @@
 use avian2d::prelude::*;
-use bevy::prelude::*;
+use bevy::{ecs::query::QueryData, prelude::*};
 use rand::prelude::*;
 
+use crate::arena::Arena;
+use crate::brain::*;
 use crate::components::*;
 use crate::config::*;
+use crate::resources::*;
 
 /// Energy
-pub fn idle_energy(mut entities: Query<&mut Energy, With<Species>>) {
-    let mut rng = rand::rng();
-    for mut energy in entities.iter_mut() {
-        energy.lose(IDLE_ENERGY_LOSS * FIXED_TIME_STEP * rng.random::<f32>());
+pub fn idle_energy(
+    settings: Res<RunSettings>,
+    config: Res<GameConfig>,
+    mut rng: ResMut<SimulationRng>,
+    mut nutrients: Option<ResMut<NutrientField>>,
+    mut entities: Query<(&mut Energy, &Transform), With<Species>>,
+) {
+    let idle_loss = config.runtime.simulation.energy.idle_loss;
+    let recycling = config
+        .runtime
+        .simulation
+        .nutrients
+        .as_ref()
+        .map_or(0.0, |nutrients| nutrients.recycling);
+    let rng = rng.stream(RngStream::Idle);
+    for (mut energy, transform) in entities.iter_mut() {
+        let energy_loss = idle_loss * settings.time_step() * rng.random::<f32>();
+        energy.lose(energy_loss);
+        if let Some(nutrients) = nutrients.as_mut() {
+            nutrients.deposit(transform.translation.truncate(), energy_loss * recycling);
+        }
     }
 }
 
+/// Photosynthesis, drawing from the local nutrients when the field is enabled
 pub fn plant_regeneration_system(
-    mut plants: Query<(&mut Energy, &Photosynthesis), With<Photosynthesis>>,
+    settings: Res<RunSettings>,
+    mut rng: ResMut<SimulationRng>,
+    mut nutrients: Option<ResMut<NutrientField>>,
+    mut plants: Query<(&mut Energy, &Photosynthesis, &Transform), With<Photosynthesis>>,
 ) {
-    let mut rng = rand::rng();
-    for (mut energy, photosynthesis) in plants.iter_mut() {
-        energy.gain(photosynthesis.value() * FIXED_TIME_STEP * rng.random::<f32>());
+    let rng = rng.stream(RngStream::Regeneration);
+    for (mut energy, photosynthesis, transform) in plants.iter_mut() {
+        let mut regeneration = photosynthesis.value() * settings.time_step() * rng.random::<f32>();
+        if let Some(nutrients) = nutrients.as_mut() {
+            // Only take what the plant can store
+            let demand = regeneration.min(energy.max - energy.value()).max(0.0);
+            regeneration = nutrients.take(transform.translation.truncate(), demand);
+        }
+        energy.gain(regeneration);
+    }
+}
+
+/// Spread the nutrients between neighbouring cells
+pub fn diffuse_nutrients(
+    settings: Res<RunSettings>,
+    config: Res<GameConfig>,
+    mut nutrients: ResMut<NutrientField>,
+) {
+    if let Some(nutrient_config) = &config.runtime.simulation.nutrients {
+        nutrients.diffuse(nutrient_config.diffusion * settings.time_step());
     }
 }
 
 pub fn movement_energy(
+    settings: Res<RunSettings>,
     mut entities: Query<(&mut Energy, &LinearVelocity, &Size), With<ActiveMover>>,
 ) {
     // todo only take into account active velocity. Take mass into account.
@@ -33,7 +75,7 @@ pub fn movement_energy(
             * size.value()
             * size.value()
             * MOVEMENT_ENERGY_COST_FACTOR
-            * FIXED_TIME_STEP;
+            * settings.time_step();
         energy.lose(energy_cost);
     }
 }
@@ -42,10 +84,25 @@ pub fn movement_energy(
 pub fn update_vision_system(
     mut query: Query<(Entity, &Transform, &Vision, &mut VisionResults)>,
     spatial_query: SpatialQuery,
+    rooted: Query<&Rooted>,
 ) {
     for (entity, transform, vision, mut results) in &mut query {
         results.rays.clear();
 
+        // Don't hit self, nor the bushes it is in. Bushes hide what is inside them, but not
+        // from within
+        let hiding_bushes: Vec<Entity> = spatial_query
+            .point_intersections(
+                transform.translation.truncate(),
+                &SpatialQueryFilter::default(),
+            )
+            .into_iter()
+            .filter(|bush| matches!(rooted.get(*bush), Ok(Rooted::Bush { .. })))
+            .collect();
+        let filter = SpatialQueryFilter::from_excluded_entities(
+            std::iter::once(entity).chain(hiding_bushes.iter().copied()),
+        );
+
         let start_angle = -vision.field_of_view / 2.0;
         let angle_step = if vision.nb_rays > 1 {
             vision.field_of_view / (vision.nb_rays - 1) as f32
@@ -63,13 +120,15 @@ pub fn update_vision_system(
 
             let direction = Dir2::new(world_direction).unwrap_or(Dir2::X);
 
+            // Grass has no collisions and hides nothing
             let hit_info = spatial_query
-                .cast_ray(
+                .cast_ray_predicate(
                     origin,
                     direction,
                     vision.detection_range,
                     true,
-                    &SpatialQueryFilter::from_excluded_entities([entity]), // Don't hit self
+                    &filter,
+                    &|hit| !matches!(rooted.get(hit), Ok(Rooted::Grass)),
                 )
                 .map(|hit| RayHitInfo {
                     entity: hit.entity,
@@ -87,80 +146,131 @@ pub fn update_vision_system(
     }
 }
 
-pub fn vision_analysis_system(
-    mut entity_query: Query<(
-        &VisionResults,
-        &Species,
-        Option<&Hunter>,
-        &mut MovementIntent,
-    )>,
-    obstacles_query: Query<(&Species, Option<&Hunter>)>,
+/// Components of an organism deciding its movement from what it perceives
+#[derive(QueryData)]
+#[query_data(mutable)]
+pub struct DecisionData {
+    entity: Entity,
+    brain: &'static mut Brain,
+    vision_results: &'static VisionResults,
+    species: &'static Species,
+    hunter: Option<&'static Hunter>,
+    scavenger: Option<&'static Scavenger>,
+    steering: &'static SteeringWeights,
+    energy: &'static mut Energy,
+    genome: &'static Genome,
+    mating_type: Option<&'static MatingType>,
+    speed: &'static Speed,
+    age: &'static Age,
+    movement_intent: &'static mut MovementIntent,
+}
+
+/// Components of an organism hit by a vision ray
+#[derive(QueryData)]
+pub struct SightedData {
+    species: &'static Species,
+    hunter: Option<&'static Hunter>,
+    mating_type: Option<&'static MatingType>,
+    velocity: Option<&'static LinearVelocity>,
+}
+
+/// Turn the vision rays and internal state of each organism into a perception, from
+/// which its brain decides where to go
+pub fn decision_system(
+    settings: Res<RunSettings>,
+    mut rng: ResMut<SimulationRng>,
+    mut external_intents: ResMut<ExternalIntents>,
+    mut entity_query: Query<DecisionData>,
+    obstacles_query: Query<SightedData>,
+    carcasses_query: Query<(&Carcass, Option<&LinearVelocity>)>,
 ) {
-    for (vision_result, species, hunter, mut movement_intent) in entity_query.iter_mut() {
-        let mut direction = Vec2::ZERO;
-
-        for ray in &vision_result.rays {
-            if let Some(hit) = &ray.hit {
-                let hit_entity = hit.entity;
-
-                let weight =
-                    if let Ok((hit_species, hit_hunter_opt)) = obstacles_query.get(hit_entity) {
-                        if let Some(ray_owner_hunter) = hunter {
-                            if ray_owner_hunter.hunts.contains(hit_species) {
-                                // Hit entity is prey for ray owner -> Green
-                                WEIGHT_PREY
-                            } else if let Some(hit_hunter) = hit_hunter_opt {
-                                // Check if ray owner is prey for the hit entity
-                                if hit_hunter.hunts.contains(species) {
-                                    // Hit entity is predator for ray owner
-                                    WEIGHT_PREDATOR
-                                } else {
-                                    // Hit entity is neither prey nor predator for ray owner
-                                    WEIGHT_NEUTRAL
-                                }
-                            } else {
-                                // Hit entity is not a hunter
-                                WEIGHT_NEUTRAL
-                            }
-                        } else {
-                            // Ray owner is not a hunter, check if hit entity is a predator
-                            if let Some(hit_hunter) = hit_hunter_opt {
-                                if hit_hunter.hunts.contains(species) {
-                                    // Hit entity is predator for ray owner
-                                    WEIGHT_PREDATOR
-                                } else {
-                                    // Hit entity doesn't hunt ray owner
-                                    WEIGHT_NEUTRAL
-                                }
-                            } else {
-                                // Hit entity is not a hunter
-                                WEIGHT_NEUTRAL
-                            }
-                        }
+    let time_step = settings.time_step();
+    let rng = rng.stream(RngStream::Behaviour);
+    for mut organism in entity_query.iter_mut() {
+        let mut burst = false;
+        let force = if let Brain::External = *organism.brain {
+            external_intents
+                .0
+                .remove(&organism.entity)
+                .unwrap_or(Vec2::ZERO)
+        } else {
+            let sightings = organism
+                .vision_results
+                .rays
+                .iter()
+                .filter_map(|ray| {
+                    let hit = ray.hit.as_ref()?;
+                    let mut mate = false;
+                    let (relation, species, velocity) = if let Ok(sighted) =
+                        obstacles_query.get(hit.entity)
+                    {
+                        mate = sighted.species == organism.species
+                            && organism.mating_type.is_some_and(|mating_type| {
+                                sighted
+                                    .mating_type
+                                    .is_some_and(|hit_type| hit_type != mating_type)
+                            });
+                        let relation = Relation::between(
+                            *organism.species,
+                            organism.hunter,
+                            *sighted.species,
+                            sighted.hunter,
+                        );
+                        (relation, Some(*sighted.species), sighted.velocity)
+                    } else if let Ok((carcass, carcass_velocity)) = carcasses_query.get(hit.entity)
+                        && organism
+                            .scavenger
+                            .is_some_and(|scavenger| scavenger.scavenges.contains(&carcass.species))
+                    {
+                        (Relation::Carrion, Some(carcass.species), carcass_velocity)
                     } else {
-                        // Hit entity has no species/hunter info
-                        WEIGHT_NEUTRAL
+                        (Relation::Obstacle, None, None)
                     };
+                    Some(Sighting {
+                        direction: ray.direction.normalize(),
+                        proximity: 1.0 - (hit.distance / ray.max_distance),
+                        distance: hit.distance,
+                        velocity: velocity.map_or(Vec2::ZERO, |velocity| velocity.0),
+                        relation,
+                        species,
+                        mate,
+                        entity: hit.entity,
+                    })
+                })
+                .collect();
+            let perception = Perception {
+                sightings,
+                energy: organism.energy.value() / organism.energy.max,
+                ready_to_mate: organism.mating_type.is_some()
+                    && organism.genome.ready_to_reproduce(&organism.energy),
+                speed: organism.speed.value(),
+                age: organism.age.0,
+            };
+            let steering = organism.steering;
+            burst = perception.bursts(steering);
+            organism.brain.decide(&perception, steering, time_step, rng)
+        };
 
-                let dist_factor = 1.0 - (hit.distance / ray.max_distance);
-                direction += ray.direction.normalize() * weight * dist_factor;
-            }
+        // Jet bursts cost energy for as long as they last
+        if burst {
+            organism.energy.lose(BURST_ENERGY_COST * time_step);
         }
+        organism.movement_intent.burst = burst;
 
-        if direction.length_squared() > 0.01 {
-            movement_intent.desired_direction = direction.normalize();
-            movement_intent.desired_force = direction;
+        if force.length_squared() > 0.01 {
+            organism.movement_intent.desired_direction = force.normalize();
+            organism.movement_intent.desired_force = force;
         } else {
-            movement_intent.desired_direction = Vec2::ZERO;
-            movement_intent.desired_force = Vec2::ZERO;
+            organism.movement_intent.desired_direction = Vec2::ZERO;
+            organism.movement_intent.desired_force = Vec2::ZERO;
         }
     }
 }
 
 pub fn apply_movement_system(
-    mut query: Query<(Forces, &Transform, &MovementIntent), With<ActiveMover>>,
+    mut query: Query<(Forces, &Transform, &MovementIntent, &Locomotion), With<ActiveMover>>,
 ) {
-    for (mut forces, transform, intent) in query.iter_mut() {
+    for (mut forces, transform, intent, locomotion) in query.iter_mut() {
         if intent.desired_direction.length_squared() < 0.001 {
             continue; // No intent, skip
         }
@@ -178,7 +288,7 @@ pub fn apply_movement_system(
 
         // === ROTATION ===
         // Apply angular acceleration based on turn error
-        let angular_accel = cross * TURN_RESPONSIVENESS;
+        let angular_accel = cross * locomotion.turn_responsiveness;
         forces.apply_angular_acceleration(angular_accel);
 
         // === LINEAR MOVEMENT ===
@@ -187,18 +297,32 @@ pub fn apply_movement_system(
             / (1.0 - FORWARD_ALIGNMENT_THRESHOLD))
             .clamp(0.0, 1.0);
 
-        // Apply linear acceleration in the FACING direction
-        let linear_accel = facing * ACCELERATION_FORCE * alignment_factor;
+        // Apply linear acceleration in the FACING direction, stronger during jet bursts
+        let burst = if intent.burst {
+            BURST_ACCELERATION
+        } else {
+            1.0
+        };
+        let linear_accel = facing * locomotion.acceleration * burst * alignment_factor;
         forces.apply_linear_acceleration(linear_accel);
     }
 }
 
 /// Life & death
+pub fn ageing(settings: Res<RunSettings>, mut organisms: Query<&mut Age>) {
+    let time_step = settings.time_step();
+    for mut age in organisms.iter_mut() {
+        age.0 += time_step;
+    }
+}
+
 pub fn collision_kill_system(
     mut commands: Commands,
+    config: Res<GameConfig>,
     mut collision_events: MessageReader<CollisionStart>,
     mut query: Query<(Option<&Hunter>, Option<&Species>, Option<&mut Energy>)>,
 ) {
+    let transfer_rate = config.runtime.simulation.energy.transfer_rate;
     for event in collision_events.read() {
         let entity1 = event.collider1;
         let entity2 = event.collider2;
@@ -215,7 +339,7 @@ pub fn collision_kill_system(
         {
             // Get part of prey energy
             if let Some(prey_energy) = entity2_comps.2.as_ref() {
-                let energy_gained = prey_energy.value() * ENERGY_TRANSFER_RATE;
+                let energy_gained = prey_energy.value() * transfer_rate;
                 if let Some(predator_energy) = entity1_comps.2.as_mut() {
                     predator_energy.gain(energy_gained);
                 }
@@ -231,7 +355,7 @@ pub fn collision_kill_system(
         {
             // Get part of prey energy
             if let Some(prey_energy) = entity1_comps.2.as_ref() {
-                let energy_gained = prey_energy.value() * ENERGY_TRANSFER_RATE;
+                let energy_gained = prey_energy.value() * transfer_rate;
                 if let Some(predator_energy) = entity2_comps.2.as_mut() {
                     predator_energy.gain(energy_gained);
                 }
@@ -242,139 +366,565 @@ pub fn collision_kill_system(
     }
 }
 
-pub fn death(mut commands: Commands, entities: Query<(Entity, &Energy), With<Species>>) {
-    for (entity, energy) in entities.iter() {
+/// Components of a dying organism, passed on to its carcass
+#[derive(QueryData)]
+pub struct BodyData {
+    entity: Entity,
+    species: &'static Species,
+    energy: &'static Energy,
+    color: &'static EntityColor,
+    size: &'static Size,
+    transform: &'static Transform,
+    linear_velocity: &'static LinearVelocity,
+}
+
+/// Starvation, leaving a carcass that keeps part of the energy of the body
+pub fn death(mut commands: Commands, config: Res<GameConfig>, entities: Query<BodyData>) {
+    let carcasses = &config.runtime.simulation.carcasses;
+    for body in entities.iter() {
+        if body.energy.value() <= 0.0 {
+            commands.entity(body.entity).despawn();
+            if carcasses.energy_ratio > 0.0 {
+                // Carcasses are darker than living organisms
+                let color = body.color.value().to_linear();
+                let energy = Energy::new(body.energy.max * carcasses.energy_ratio, body.energy.max);
+                spawn_carcass(
+                    &mut commands,
+                    carcasses,
+                    *body.species,
+                    [color.red, color.green, color.blue].map(|channel| channel * 0.4),
+                    body.size.value(),
+                    energy,
+                )
+                .insert((
+                    body.transform
+                        .with_translation(body.transform.translation.with_z(Z_CARCASSES)),
+                    *body.linear_velocity,
+                ));
+            }
+        }
+    }
+}
+
+/// Spawn the carcass of an organism of the given species. Its position and velocity are
+/// left to the caller
+pub fn spawn_carcass<'a>(
+    commands: &'a mut Commands,
+    carcasses: &CarcassConfig,
+    species: Species,
+    color: [f32; 3],
+    size: f32,
+    energy: Energy,
+) -> EntityCommands<'a> {
+    commands.spawn((
+        RigidBody::Dynamic,
+        Friction::new(0.5),
+        LinearDamping(carcasses.drag),
+        AngularDamping(ANGULAR_DAMPING),
+        CollisionEventsEnabled,
+        Name::new("Carcass"),
+        Carcass { species },
+        EntityColor::new(color[0], color[1], color[2]),
+        Size::new(size),
+        Collider::circle(size),
+        energy,
+    ))
+}
+
+/// Fix a plant in place, bushes and grass letting organisms through
+pub fn root(entity_commands: &mut EntityCommands, rooted: Rooted) {
+    entity_commands.insert((rooted, RigidBody::Static));
+    if rooted != Rooted::Tree {
+        entity_commands.insert(Sensor);
+    }
+}
+
+/// Bushes slow down the organisms moving through them
+pub fn bush_drag(
+    settings: Res<RunSettings>,
+    spatial_query: SpatialQuery,
+    rooted: Query<&Rooted>,
+    mut movers: Query<(&Transform, &mut LinearVelocity), With<ActiveMover>>,
+) {
+    for (transform, mut velocity) in movers.iter_mut() {
+        let position = transform.translation.truncate();
+        for entity in spatial_query.point_intersections(position, &SpatialQueryFilter::default()) {
+            if let Ok(Rooted::Bush { density }) = rooted.get(entity) {
+                velocity.0 *= (-density * settings.time_step()).exp();
+            }
+        }
+    }
+}
+
+/// Carcasses lose energy until eaten or gone, returning it to the nutrients
+pub fn carcass_decay(
+    mut commands: Commands,
+    settings: Res<RunSettings>,
+    config: Res<GameConfig>,
+    mut nutrients: Option<ResMut<NutrientField>>,
+    mut carcasses: Query<(Entity, &mut Energy, &Transform), With<Carcass>>,
+) {
+    let decay_rate = config.runtime.simulation.carcasses.decay_rate;
+    for (entity, mut energy, transform) in carcasses.iter_mut() {
+        let energy_loss = (energy.max * decay_rate * settings.time_step()).min(energy.value());
+        energy.lose(energy_loss);
+        if let Some(nutrients) = nutrients.as_mut() {
+            nutrients.deposit(transform.translation.truncate(), energy_loss);
+        }
         if energy.value() <= 0.0 {
             commands.entity(entity).despawn();
         }
     }
 }
 
-pub fn reproduction(
+/// Scavengers eat the carcasses of the species they scavenge on contact
+pub fn scavenging(
     mut commands: Commands,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<ColorMaterial>>,
-    mut entities: Query<
+    config: Res<GameConfig>,
+    mut collision_events: MessageReader<CollisionStart>,
+    mut scavengers: Query<(&Scavenger, &mut Energy), Without<Carcass>>,
+    carcasses: Query<(&Carcass, &Energy)>,
+) {
+    let transfer_rate = config.runtime.simulation.energy.transfer_rate;
+    let mut eaten = Vec::new();
+    for event in collision_events.read() {
+        for (entity1, entity2) in [
+            (event.collider1, event.collider2),
+            (event.collider2, event.collider1),
+        ] {
+            let (Ok((scavenger, mut scavenger_energy)), Ok((carcass, carcass_energy))) =
+                (scavengers.get_mut(entity1), carcasses.get(entity2))
+            else {
+                continue;
+            };
+            if !scavenger.scavenges.contains(&carcass.species) || eaten.contains(&entity2) {
+                continue;
+            }
+            scavenger_energy.gain(carcass_energy.value() * transfer_rate);
+            commands.entity(entity2).despawn();
+            eaten.push(entity2);
+        }
+    }
+}
+
+/// Components of a reproducing organism, inherited by its child
+#[derive(QueryData)]
+#[query_data(mutable)]
+pub struct ParentData {
+    name: &'static Name,
+    species: &'static Species,
+    energy: &'static mut Energy,
+    genome: &'static Genome,
+    hunter: Option<&'static Hunter>,
+    scavenger: Option<&'static Scavenger>,
+    photosynthesis: Option<&'static Photosynthesis>,
+    vision: Option<&'static Vision>,
+    steering: Option<&'static SteeringWeights>,
+    brain: Option<&'static Brain>,
+    density: &'static ColliderDensity,
+    active_mover: Option<&'static ActiveMover>,
+    locomotion: Option<&'static Locomotion>,
+    mating_type: Option<&'static MatingType>,
+    rooted: Option<&'static Rooted>,
+    dispersal: Option<&'static Dispersal>,
+    transform: &'static Transform,
+}
+impl ParentDataItem<'_, '_> {
+    /// Child inheriting the components of this parent, with its own energy and genome
+    fn child(&self, energy: Energy, genome: Genome) -> Child {
+        Child {
+            name: self.name.clone(),
+            species: *self.species,
+            energy,
+            genome,
+            hunter: self.hunter.cloned(),
+            scavenger: self.scavenger.cloned(),
+            photosynthesis: self.photosynthesis.cloned(),
+            vision: self.vision.cloned(),
+            steering: self.steering.cloned(),
+            brain: self.brain.map(Brain::newborn),
+            density: *self.density,
+            active_mover: self.active_mover.cloned(),
+            locomotion: self.locomotion.cloned(),
+            mating_type: self.mating_type.copied(),
+            rooted: self.rooted.copied(),
+            dispersal: self.dispersal.copied(),
+            transform: *self.transform,
+        }
+    }
+}
+
+/// Child to be spawned, its phenotype derived from its genome
+#[derive(Clone)]
+struct Child {
+    name: Name,
+    species: Species,
+    energy: Energy,
+    genome: Genome,
+    hunter: Option<Hunter>,
+    scavenger: Option<Scavenger>,
+    photosynthesis: Option<Photosynthesis>,
+    vision: Option<Vision>,
+    steering: Option<SteeringWeights>,
+    brain: Option<Brain>,
+    density: ColliderDensity,
+    active_mover: Option<ActiveMover>,
+    locomotion: Option<Locomotion>,
+    mating_type: Option<MatingType>,
+    rooted: Option<Rooted>,
+    dispersal: Option<Dispersal>,
+    transform: Transform,
+}
+
+fn spawn_child(commands: &mut Commands, rng: &mut impl Rng, child: Child) {
+    let genome = child.genome;
+    let mut entity_commands = commands.spawn((
         (
-            &Name,
-            &EntityColor,
-            &Species,
-            &mut Energy,
-            Option<&Hunter>,
-            Option<&Photosynthesis>,
-            Option<&Vision>,
-            &Speed,
-            &Size,
-            Option<&ActiveMover>,
-            &Transform,
-            &LinearVelocity,
+            RigidBody::Dynamic,
+            Restitution::new(0.2), // Bouncing restitution
+            Friction::new(0.5),
+            LinearDamping(LINEAR_DAMPING),
+            AngularDamping(ANGULAR_DAMPING),
+            CollisionEventsEnabled,
+            Consumable,
         ),
-        With<Species>,
-    >,
+        child.name,
+        EntityColor::new(genome.color[0], genome.color[1], genome.color[2]),
+        child.species,
+        child.energy,
+        Speed::new(genome.speed),
+        Size::new(genome.size),
+        child.transform,
+        LinearVelocity(Vec2::new(
+            (10.0 + genome.speed) * (rng.random::<f32>() * 2.0 - 1.0),
+            (10.0 + genome.speed) * (rng.random::<f32>() * 2.0 - 1.0),
+        )),
+        Collider::circle(genome.size),
+        child.density,
+        MovementIntent::default(),
+        Age::default(),
+    ));
+    if let Some(hunter_component) = child.hunter {
+        entity_commands.insert(hunter_component);
+    }
+    if let Some(scavenger_component) = child.scavenger {
+        entity_commands.insert(scavenger_component);
+    }
+    if let Some(photosynthesis_component) = child.photosynthesis {
+        entity_commands.insert(photosynthesis_component);
+    }
+    if let Some(active_mover_component) = child.active_mover {
+        entity_commands.insert(active_mover_component);
+    }
+    if let Some(locomotion_component) = child.locomotion {
+        entity_commands.insert(locomotion_component);
+    }
+    if let Some(vision_component) = child.vision {
+        entity_commands.insert(Vision::new(
+            genome.detection_range,
+            vision_component.nb_rays,
+            genome.field_of_view,
+        ));
+        entity_commands.insert(VisionResults::default());
+    }
+    if let Some(steering_component) = child.steering {
+        entity_commands.insert(SteeringWeights {
+            prey: genome.boldness,
+            predator: -genome.fearfulness,
+            conspecific: genome.sociability,
+            ..steering_component
+        });
+    }
+    if let Some(brain_component) = child.brain {
+        entity_commands.insert(brain_component);
+    }
+    if let Some(mating_type_component) = child.mating_type {
+        entity_commands.insert(mating_type_component);
+    }
+    if let Some(rooted) = child.rooted {
+        root(&mut entity_commands, rooted);
+    }
+    if let Some(dispersal_component) = child.dispersal {
+        entity_commands.insert(dispersal_component);
+    }
+    entity_commands.insert(genome);
+}
+
+/// Spawn a child, or a seed carrying it for plants dispersing their seeds
+fn propagate(commands: &mut Commands, rng: &mut impl Rng, child: Child) {
+    let Some(dispersal) = child.dispersal else {
+        spawn_child(commands, rng, child);
+        return;
+    };
+    let seed = Seed {
+        velocity: Vec2::from_angle(rng.random_range(0.0..std::f32::consts::TAU))
+            * dispersal.speed
+            * rng.random::<f32>(),
+        age: 0.0,
+        carrier: None,
+        dispersal,
+        child,
+    };
+    spawn_seed(commands, seed);
+}
+
+fn spawn_seed(commands: &mut Commands, seed: Seed) {
+    let genome = &seed.child.genome;
+    commands.spawn((
+        Name::new("Seed"),
+        EntityColor::new(genome.color[0], genome.color[1], genome.color[2]),
+        Size::new((genome.size / 4.0).max(1.0)),
+        seed.child.transform,
+        seed,
+    ));
+}
+
+/// Spawn the seed of a snapshot, carrying a plant of its species as the lore describes
+/// it. Carried seeds are released and cling again to the animals still touching them
+pub fn restore_seed(
+    commands: &mut Commands,
+    snapshot: &SeedSnapshot,
+    species: Species,
+    params: &EntitySpawnParams,
 ) {
-    // Prepare children common attributes
-    let entity_bundle = (
-        RigidBody::Dynamic,
-        Restitution::new(0.2), // Bouncing restitution
-        Friction::new(0.5),
-        LinearDamping(LINEAR_DAMPING),
-        AngularDamping(ANGULAR_DAMPING),
-        ColliderDensity(1.0), // Add density so mass is computed from collider
-        CollisionEventsEnabled,
-        Consumable,
-    );
+    let dispersal = params
+        .dispersal
+        .expect("Snapshot seed species checked to disperse seeds");
+    let child = Child {
+        name: Name::new("Plant"),
+        species,
+        energy: Energy::new(snapshot.energy, snapshot.max_energy),
+        genome: snapshot.genome.clone(),
+        hunter: None,
+        scavenger: None,
+        photosynthesis: snapshot.photosynthesis.map(Photosynthesis::new),
+        vision: None,
+        steering: None,
+        brain: None,
+        density: ColliderDensity(params.density),
+        active_mover: None,
+        locomotion: None,
+        mating_type: snapshot.mating_type.map(MatingType),
+        rooted: params.rooted,
+        dispersal: Some(dispersal),
+        transform: Transform::from_xyz(snapshot.position[0], snapshot.position[1], Z_ENTITIES),
+    };
+    let seed = Seed {
+        velocity: Vec2::from_array(snapshot.velocity),
+        age: dispersal.drift_time - snapshot.drift_time,
+        carrier: None,
+        dispersal,
+        child,
+    };
+    spawn_seed(commands, seed);
+}
+
+/// Seed drifting until it germinates into the plant it carries
+#[derive(Component)]
+pub struct Seed {
+    child: Child,
+    dispersal: Dispersal,
+    velocity: Vec2,
+    /// Seconds since the seed was released
+    age: f32,
+    /// Animal the seed clings to
+    carrier: Option<Entity>,
+}
+impl Seed {
+    pub fn species(&self) -> Species {
+        self.child.species
+    }
+
+    /// Energy of the plant carried
+    pub fn energy(&self) -> &Energy {
+        &self.child.energy
+    }
+
+    pub fn snapshot(&self, position: Vec2, species_registry: &SpeciesRegistry) -> SeedSnapshot {
+        SeedSnapshot {
+            species: species_registry.key(self.child.species).to_string(),
+            position: position.to_array(),
+            velocity: self.velocity.to_array(),
+            drift_time: self.dispersal.drift_time - self.age,
+            energy: self.child.energy.current,
+            max_energy: self.child.energy.max,
+            genome: self.child.genome.clone(),
+            mating_type: self.child.mating_type.map(|mating_type| mating_type.0),
+            photosynthesis: self
+                .child
+                .photosynthesis
+                .as_ref()
+                .map(Photosynthesis::value),
+        }
+    }
+}
+
+/// Move the seeds with their momentum and the water current, or with the animal carrying
+/// them, keeping them in the water. Seeds reaching the shore or an obstacle lodge there
+pub fn seed_drift(
+    settings: Res<RunSettings>,
+    config: Res<GameConfig>,
+    arena: Res<Arena>,
+    spatial_query: SpatialQuery,
+    mut seeds: Query<(&mut Seed, &mut Transform)>,
+    carriers: Query<&Transform, (With<ActiveMover>, Without<Seed>)>,
+) {
+    let time_step = settings.time_step();
+    let current = Vec2::from_array(config.runtime.simulation.current);
+    for (mut seed, mut transform) in seeds.iter_mut() {
+        seed.age += time_step;
+        let drifting = seed.age < seed.dispersal.drift_time;
+
+        // Follow the carrier until the drift time is over or the carrier is gone
+        if let Some(carrier) = seed.carrier {
+            if drifting && let Ok(carrier_transform) = carriers.get(carrier) {
+                transform.translation = carrier_transform
+                    .translation
+                    .truncate()
+                    .extend(transform.translation.z);
+                continue;
+            }
+            seed.carrier = None;
+        }
+        let position = transform.translation.truncate();
+        if drifting && seed.dispersal.carried {
+            seed.carrier = spatial_query
+                .point_intersections(position, &SpatialQueryFilter::default())
+                .into_iter()
+                .find(|entity| carriers.contains(*entity));
+        }
+
+        let drag = (-SEED_DRAG * time_step).exp();
+        seed.velocity *= drag;
+        let next = position + (seed.velocity + current) * time_step;
+        if arena.contains(next, 0.0) {
+            transform.translation = next.extend(transform.translation.z);
+        } else {
+            seed.velocity = Vec2::ZERO;
+        }
+    }
+}
+
+/// Germinate the seeds done drifting in free space that is not crowded with plants. Seeds
+/// failing to germinate return their energy to the nutrients
+pub fn germination(
+    mut commands: Commands,
+    mut rng: ResMut<SimulationRng>,
+    mut nutrients: Option<ResMut<NutrientField>>,
+    spatial_query: SpatialQuery,
+    seeds: Query<(Entity, &Seed, &Transform)>,
+    plants: Query<&Transform, With<Photosynthesis>>,
+) {
+    let rng = rng.stream(RngStream::Dispersal);
+    let mut germinated: Vec<Vec2> = Vec::new();
+    for (entity, seed, transform) in seeds.iter() {
+        if seed.carrier.is_some() || seed.age < seed.dispersal.drift_time {
+            continue;
+        }
+        commands.entity(entity).despawn();
+
+        let position = transform.translation.truncate();
+        let free = spatial_query
+            .point_intersections(position, &SpatialQueryFilter::default())
+            .is_empty();
+        let radius_squared = seed.dispersal.crowding_radius.powi(2);
+        let crowding = plants
+            .iter()
+            .map(|plant| plant.translation.truncate())
+            .chain(germinated.iter().copied())
+            .filter(|plant| plant.distance_squared(position) <= radius_squared)
+            .count();
+        if free && crowding < seed.dispersal.crowding as usize {
+            let mut child = seed.child.clone();
+            child.transform.translation = position.extend(child.transform.translation.z);
+            spawn_child(&mut commands, rng, child);
+            germinated.push(position);
+        } else if let Some(nutrients) = nutrients.as_mut() {
+            nutrients.deposit(position, seed.child.energy.value());
+        }
+    }
+}
 
+/// Asexual reproduction, splitting organisms above their reproduction threshold
+pub fn reproduction(
+    mut commands: Commands,
+    config: Res<GameConfig>,
+    mut rng: ResMut<SimulationRng>,
+    mut entities: Query<ParentData, (With<Species>, Without<MatingType>)>,
+) {
     // Get parents
-    let mut parents = Vec::new();
-    for (
-        name,
-        color,
-        species,
-        mut energy,
-        hunter,
-        photosynthesis,
-        vision,
-        speed,
-        size,
-        active_mover,
-        transform,
-        linear_velocity,
-    ) in entities.iter_mut()
-    {
-        if energy.value() >= 0.8 * energy.max {
+    let mut children = Vec::new();
+    for mut parent in entities.iter_mut() {
+        if parent.genome.ready_to_reproduce(&parent.energy) {
             // Lose energy (10%)
             // then divide it by two so it is shared between the entity and its child
-            let energy_loss = energy.value() * 0.55;
-            energy.lose(energy_loss);
+            let energy_loss = parent.energy.value() * 0.55;
+            parent.energy.lose(energy_loss);
 
             // Adds to parents
-            parents.push((
-                name.clone(),
-                color.clone(),
-                *species,
-                energy.clone(),
-                hunter.cloned(),
-                photosynthesis.cloned(),
-                vision.cloned(),
-                speed.clone(),
-                size.clone(),
-                active_mover.cloned(),
-                *transform,
-                *linear_velocity,
-            ))
+            children.push(parent.child(parent.energy.clone(), parent.genome.clone()));
         }
     }
 
-    // Spawn children (perfect clones)
-    for (
-        name,
-        color,
-        species,
-        energy,
-        hunter,
-        photosynthesis,
-        vision,
-        speed,
-        size,
-        active_mover,
-        transform,
-        _linear_velocity,
-    ) in parents
-    {
-        let speed_value = speed.value();
-        let size_value = size.value();
-        let color_value = color.value();
-        let circle = Circle::new(size_value);
-        let mut child = commands.spawn((
-            entity_bundle.clone(),
-            name,
-            color,
-            species,
-            energy,
-            speed,
-            size,
-            transform,
-            LinearVelocity(Vec2::new(
-                (10.0 + speed_value) * (rand::random::<f32>() * 2.0 - 1.0),
-                (10.0 + speed_value) * (rand::random::<f32>() * 2.0 - 1.0),
-            )),
-            Collider::circle(size_value),
-            Mesh2d(meshes.add(circle)),
-            MeshMaterial2d(materials.add(color_value)),
-            MovementIntent::default(),
-        ));
-        if let Some(hunter_component) = hunter {
-            child.insert(hunter_component);
-        }
-        if let Some(photosynthesis_component) = photosynthesis {
-            child.insert(photosynthesis_component);
+    // Spawn children, inheriting the parent genome and network with mutations
+    let rng = rng.stream(RngStream::Reproduction);
+    for mut child in children {
+        child.genome = child
+            .genome
+            .mutated(&config.runtime.mutations[child.species.index()], rng);
+        child.brain = child.brain.map(|brain| brain.mutated(rng));
+        propagate(&mut commands, rng, child);
+    }
+}
+
+/// Sexual reproduction, when two ready conspecifics of different mating types meet
+pub fn mating(
+    mut commands: Commands,
+    config: Res<GameConfig>,
+    mut rng: ResMut<SimulationRng>,
+    mut collision_events: MessageReader<CollisionStart>,
+    mut entities: Query<ParentData, (With<Species>, With<MatingType>)>,
+) {
+    let rng = rng.stream(RngStream::Mating);
+    for event in collision_events.read() {
+        let Ok([mut parent1, mut parent2]) =
+            entities.get_many_mut([event.collider1, event.collider2])
+        else {
+            continue;
+        };
+        if parent1.species != parent2.species
+            || parent1.mating_type == parent2.mating_type
+            || !parent1.genome.ready_to_reproduce(&parent1.energy)
+            || !parent2.genome.ready_to_reproduce(&parent2.energy)
+        {
+            continue;
         }
-        if let Some(active_mover_component) = active_mover {
-            child.insert(active_mover_component);
+
+        // Each parent loses 30% of its energy, 25% going to the child
+        let mut energy = Energy::new(0.0, parent1.energy.max);
+        for parent in [&mut parent1, &mut parent2] {
+            let energy_loss = parent.energy.value() * 0.3;
+            energy.gain(parent.energy.value() * 0.25);
+            parent.energy.lose(energy_loss);
         }
-        if let Some(vision_component) = vision {
-            child.insert(vision_component);
-            child.insert(VisionResults::default());
+
+        // The child recombines the genomes of its parents, with mutations, and takes the
+        // mating type of either
+        let genome = parent1
+            .genome
+            .recombined(parent2.genome, rng)
+            .mutated(&config.runtime.mutations[parent1.species.index()], rng);
+        let mut child = if rng.random_bool(0.5) {
+            parent1.child(energy, genome)
+        } else {
+            parent2.child(energy, genome)
+        };
+        child.transform.translation =
+            (parent1.transform.translation + parent2.transform.translation) / 2.0;
+        // Neural brains recombine the networks of both parents too
+        if let (Some(brain1), Some(brain2)) = (parent1.brain, parent2.brain) {
+            child.brain = Some(brain1.newborn().recombined(brain2, rng).mutated(rng));
         }
+        propagate(&mut commands, rng, child);
     }
 }
@@ -0,0 +1,154 @@
+use avian2d::prelude::*;
+use bevy::{
+    diagnostic::FrameCount,
+    ecs::{query::QueryData, system::SystemParam},
+    prelude::*,
+};
+
+use crate::components::*;
+use crate::resources::*;
+use crate::systems::Seed;
+
+/// Components saved in a snapshot
+#[derive(QueryData)]
+pub struct SnapshotData {
+    entity: Entity,
+    species: Option<&'static Species>,
+    carcass: Option<&'static Carcass>,
+    color: &'static EntityColor,
+    energy: &'static Energy,
+    size: &'static Size,
+    speed: Option<&'static Speed>,
+    transform: &'static Transform,
+    linear_velocity: &'static LinearVelocity,
+    angular_velocity: &'static AngularVelocity,
+    hunter: Option<&'static Hunter>,
+    vision: Option<&'static Vision>,
+    photosynthesis: Option<&'static Photosynthesis>,
+    genome: Option<&'static Genome>,
+    mating_type: Option<&'static MatingType>,
+    age: Option<&'static Age>,
+    brain: Option<&'static Brain>,
+}
+
+/// Organisms, carcasses and seeds saved in a snapshot
+#[derive(SystemParam)]
+pub struct SnapshotEntities<'w, 's> {
+    entities: Query<'w, 's, SnapshotData, OrganismOrCarcass>,
+    seeds: Query<'w, 's, (Entity, &'static Seed, &'static Transform)>,
+}
+
+/// Save the world at the requested simulated times
+pub fn save_snapshots(
+    frame_count: Res<FrameCount>,
+    settings: Res<RunSettings>,
+    config: Res<GameConfig>,
+    rng: Res<SimulationRng>,
+    nutrients: Option<Res<NutrientField>>,
+    simulation_metadata: Res<SimulationMetadata>,
+    world: SnapshotEntities,
+) {
+    let frame = frame_count.0 + 1;
+    if !settings
+        .snapshot_at
+        .iter()
+        .any(|time| (time * settings.framerate).round() as u32 == frame)
+    {
+        return;
+    }
+
+    let species_registry = &config.runtime.species;
+
+    // Sort entities by index so that the file does not depend on query order. Indices are
+    // recycled after despawns, so this is not spawn order: a restored run is reproducible
+    // from its snapshot, but does not replay the original run draw for draw
+    let mut entities: Vec<_> = world.entities.iter().collect();
+    entities.sort_by_key(|data| data.entity.index());
+    let entities = entities
+        .into_iter()
+        .map(|data| EntitySnapshot {
+            species: data
+                .species
+                .copied()
+                .or(data.carcass.map(|carcass| carcass.species))
+                .map(|species| species_registry.key(species).to_string())
+                .unwrap_or_default(),
+            energy: data.energy.current,
+            max_energy: data.energy.max,
+            size: data.size.value(),
+            speed: data.speed.map_or(0.0, Speed::value),
+            position: data.transform.translation.truncate().to_array(),
+            rotation: data.transform.rotation.to_euler(EulerRot::XYZ).2,
+            linear_velocity: data.linear_velocity.0.to_array(),
+            angular_velocity: data.angular_velocity.0,
+            hunts: data.hunter.map(|hunter| {
+                hunter
+                    .hunts
+                    .iter()
+                    .map(|prey| species_registry.key(*prey).to_string())
+                    .collect()
+            }),
+            vision: data.vision.map(|vision| VisionSnapshot {
+                detection_range: vision.detection_range,
+                nb_rays: vision.nb_rays,
+                field_of_view: vision.field_of_view,
+            }),
+            photosynthesis: data.photosynthesis.map(Photosynthesis::value),
+            genome: data.genome.cloned(),
+            mating_type: data.mating_type.map(|mating_type| mating_type.0),
+            age: data.age.map_or(0.0, |age| age.0),
+            network: data.brain.and_then(Brain::network).cloned(),
+            behaviour: match data.brain {
+                Some(Brain::Behaviour {
+                    state,
+                    elapsed,
+                    heading,
+                    ..
+                }) => Some(BehaviourSnapshot {
+                    state: *state,
+                    elapsed: *elapsed,
+                    heading: heading.to_array(),
+                }),
+                _ => None,
+            },
+            carcass: data.carcass.map(|_| {
+                let color = data.color.value().to_linear();
+                CarcassSnapshot {
+                    color: [color.red, color.green, color.blue],
+                }
+            }),
+        })
+        .collect();
+    let mut seeds: Vec<_> = world.seeds.iter().collect();
+    seeds.sort_by_key(|(entity, _, _)| entity.index());
+    let seeds = seeds
+        .into_iter()
+        .map(|(_, seed, transform)| {
+            seed.snapshot(transform.translation.truncate(), species_registry)
+        })
+        .collect();
+
+    let snapshot = WorldSnapshot {
+        version: SNAPSHOT_VERSION,
+        biome: config.runtime.simulation.biome.clone(),
+        frame,
+        framerate: settings.framerate,
+        seed: settings.seed,
+        rng_positions: rng.positions(),
+        entities,
+        seeds,
+        nutrients: nutrients.map(|nutrients| nutrients.values().to_vec()),
+    };
+    let snapshots_dir = format!("{}/snapshots", simulation_metadata.path_dir);
+    let snapshot_path = format!("{}/frame_{:06}.toml", snapshots_dir, frame);
+    let result = std::fs::create_dir_all(&snapshots_dir).and_then(|_| {
+        std::fs::write(
+            &snapshot_path,
+            toml::to_string(&snapshot).expect("Failed to serialize snapshot."),
+        )
+    });
+    match result {
+        Ok(()) => println!("Snapshot saved to {}", snapshot_path),
+        Err(e) => eprintln!("Failed to write {}: {}", snapshot_path, e),
+    }
+}
@@ -0,0 +1,94 @@
+use bevy::{diagnostic::FrameCount, prelude::*};
+
+use crate::components::*;
+use crate::resources::*;
+
+/// Append the populations and mean energies of this frame to the statistics file
+pub fn record_statistics(
+    frame_count: Res<FrameCount>,
+    settings: Res<RunSettings>,
+    species_registry: Res<SpeciesRegistry>,
+    stop_state: Res<StopState>,
+    mut statistics: ResMut<StatisticsWriter>,
+    entities: Query<(&Species, &Energy)>,
+) {
+    // Nothing more to record once stopped, the video capture may still be finishing
+    if stop_state.reason.is_some() && !stop_state.is_changed() {
+        return;
+    }
+
+    // Population and total energy per species
+    let mut rows = vec![(0, 0.0); species_registry.len()];
+    for (species, energy) in &entities {
+        let row = &mut rows[species.index()];
+        row.0 += 1;
+        row.1 += energy.value();
+    }
+    for (population, energy) in rows.iter_mut() {
+        if *population > 0 {
+            *energy /= *population as f32;
+        }
+    }
+
+    let frame = frame_count.0 + 1;
+    let mut result = statistics.write_row(frame, frame as f32 * settings.time_step(), &rows);
+    if stop_state.reason.is_some() {
+        result = result.and_then(|_| statistics.flush());
+    }
+    if let Err(e) = result {
+        eprintln!("Failed to write statistics: {}", e);
+    }
+}
+
+/// Append the mean genome of each living species, once per simulated second
+pub fn record_traits(
+    frame_count: Res<FrameCount>,
+    settings: Res<RunSettings>,
+    species_registry: Res<SpeciesRegistry>,
+    stop_state: Res<StopState>,
+    mut traits: ResMut<TraitsWriter>,
+    entities: Query<(&Species, &Genome)>,
+) {
+    if stop_state.reason.is_some() && !stop_state.is_changed() {
+        return;
+    }
+    let frame = frame_count.0 + 1;
+    if !frame.is_multiple_of(settings.framerate.max(1.0) as u32) && stop_state.reason.is_none() {
+        return;
+    }
+
+    // Sum of each gene per species
+    let mut sums = vec![(0, [0.0; Gene::ALL.len()]); species_registry.len()];
+    for (species, genome) in &entities {
+        let (population, genes) = &mut sums[species.index()];
+        *population += 1;
+        for gene in Gene::ALL {
+            genes[gene as usize] += genome.value(gene);
+        }
+    }
+
+    let time = frame as f32 * settings.time_step();
+    let mut result = Ok(());
+    for species in species_registry.iter() {
+        let (population, genes) = &sums[species.index()];
+        if *population == 0 {
+            continue;
+        }
+        let means = genes.map(|sum| sum / *population as f32);
+        result = result.and_then(|_| {
+            traits.write_row(
+                frame,
+                time,
+                species_registry.key(species),
+                *population,
+                &means,
+            )
+        });
+    }
+    if stop_state.reason.is_some() {
+        result = result.and_then(|_| traits.flush());
+    }
+    if let Err(e) = result {
+        eprintln!("Failed to write traits: {}", e);
+    }
+}
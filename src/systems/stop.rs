@@ -0,0 +1,199 @@
+use bevy::{app::AppExit, diagnostic::FrameCount, prelude::*};
+use std::collections::VecDeque;
+
+use crate::components::*;
+use crate::config::*;
+use crate::resources::*;
+
+/// Evaluate the configured stop conditions and record the first one met
+pub fn check_stop_conditions(
+    frame_count: Res<FrameCount>,
+    settings: Res<RunSettings>,
+    config: Res<GameConfig>,
+    species_registry: Res<SpeciesRegistry>,
+    mut stop_state: ResMut<StopState>,
+    mut history: Local<VecDeque<Vec<u32>>>,
+    entities: Query<&Species>,
+) {
+    if stop_state.reason.is_some() {
+        return;
+    }
+
+    let simulation = &config.runtime.simulation;
+    let stop = &simulation.stop;
+    let frame = frame_count.0 + 1; // Frames simulated, including this one
+
+    // Count populations
+    let mut populations = vec![0; species_registry.len()];
+    for species in &entities {
+        populations[species.index()] += 1;
+    }
+    let population_of = |key: &str| {
+        species_registry
+            .get(key)
+            .map_or(0, |species| populations[species.index()])
+    };
+
+    // Fauna species that were present at the start of the run
+    let current_biome = &config.runtime.lore.biomes[&simulation.biome];
+    let mut fauna: Vec<&String> = current_biome
+        .species
+        .iter()
+        .filter(|(species_key, species_data)| {
+            species_data.species_type == "Fauna"
+                && simulation
+                    .populations
+                    .get(*species_key)
+                    .is_some_and(|population| *population > 0)
+        })
+        .map(|(species_key, _)| species_key)
+        .collect();
+    fauna.sort();
+
+    // Keep one population sample per simulated second for equilibrium detection
+    if let Some(equilibrium) = &stop.equilibrium
+        && frame.is_multiple_of(settings.framerate.max(1.0) as u32)
+    {
+        history.push_back(populations.clone());
+        let window = equilibrium.window.ceil() as usize + 1;
+        while history.len() > window {
+            history.pop_front();
+        }
+    }
+
+    let reason = if frame >= settings.max_frames() {
+        Some(StopReason::MaxDuration)
+    } else if let Some(extinction) = stop.extinction
+        && let extinct = fauna
+            .iter()
+            .filter(|species_key| population_of(species_key) == 0)
+            .map(|species_key| species_key.as_str())
+            .collect::<Vec<_>>()
+        && match extinction {
+            Extinction::Any => !extinct.is_empty(),
+            Extinction::All => !fauna.is_empty() && extinct.len() == fauna.len(),
+        }
+    {
+        Some(StopReason::Extinction(match extinction {
+            Extinction::Any => extinct.join(", "),
+            Extinction::All => "all fauna".to_string(),
+        }))
+    } else if let Some((species_key, threshold)) = stop
+        .population_threshold
+        .iter()
+        .find(|(species_key, threshold)| population_of(species_key) >= **threshold)
+    {
+        Some(StopReason::PopulationThreshold(
+            species_key.clone(),
+            *threshold,
+        ))
+    } else if let Some(min_total) = stop.min_total_population
+        && populations.iter().sum::<u32>() < min_total
+    {
+        Some(StopReason::PopulationBelow(min_total))
+    } else if let Some(equilibrium) = &stop.equilibrium
+        && history.len() > equilibrium.window.ceil() as usize
+        && is_at_equilibrium(&history, equilibrium.tolerance)
+    {
+        Some(StopReason::Equilibrium)
+    } else {
+        None
+    };
+
+    if let Some(reason) = reason {
+        println!("Stop condition met at frame {}: {}", frame, reason);
+        stop_state.reason = Some(reason);
+    }
+}
+
+/// Write the stop reason and final populations to the simulation folder
+pub fn write_stop_report(
+    frame_count: Res<FrameCount>,
+    settings: Res<RunSettings>,
+    species_registry: Res<SpeciesRegistry>,
+    simulation_metadata: Res<SimulationMetadata>,
+    stop_state: Res<StopState>,
+    entities: Query<&Species>,
+) {
+    let Some(reason) = &stop_state.reason else {
+        return;
+    };
+    if !stop_state.is_changed() {
+        return;
+    }
+
+    let mut populations = vec![0; species_registry.len()];
+    for species in &entities {
+        populations[species.index()] += 1;
+    }
+
+    let frame = frame_count.0 + 1;
+    let report = StopReport {
+        reason: reason.to_string(),
+        frame,
+        time: frame as f32 * settings.time_step(),
+        populations: species_registry
+            .iter()
+            .map(|species| {
+                (
+                    species_registry.key(species).to_string(),
+                    populations[species.index()],
+                )
+            })
+            .collect(),
+    };
+    let report_path = format!("{}/stop.toml", simulation_metadata.path_dir);
+    if let Err(e) = std::fs::write(
+        &report_path,
+        toml::to_string(&report).expect("Failed to serialize stop report."),
+    ) {
+        eprintln!("Failed to write {}: {}", report_path, e);
+    }
+}
+
+/// Exit once a stop condition is met, when no video capture has to be finalized
+pub fn exit_on_stop(stop_state: Res<StopState>, mut app_exit: MessageWriter<AppExit>) {
+    if stop_state.reason.is_some() {
+        app_exit.write(AppExit::Success);
+    }
+}
+
+/// Every population stayed within `tolerance` of its mean over the sampled history
+fn is_at_equilibrium(history: &VecDeque<Vec<u32>>, tolerance: f32) -> bool {
+    let nb_species = history.front().map_or(0, Vec::len);
+    (0..nb_species).all(|index| {
+        let samples = history.iter().map(|populations| populations[index] as f32);
+        let min = samples.clone().fold(f32::INFINITY, f32::min);
+        let max = samples.clone().fold(0.0, f32::max);
+        let mean = samples.sum::<f32>() / history.len() as f32;
+        max - min <= tolerance * mean
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn history(samples: &[[u32; 2]]) -> VecDeque<Vec<u32>> {
+        samples
+            .iter()
+            .map(|populations| populations.to_vec())
+            .collect()
+    }
+
+    #[test]
+    fn populations_within_tolerance_are_at_equilibrium() {
+        let stable = history(&[[100, 10], [105, 10], [95, 10]]);
+        assert!(is_at_equilibrium(&stable, 0.1));
+        assert!(!is_at_equilibrium(&stable, 0.05));
+    }
+
+    #[test]
+    fn any_drifting_population_breaks_equilibrium() {
+        let drifting = history(&[[100, 10], [100, 12], [100, 14]]);
+        assert!(!is_at_equilibrium(&drifting, 0.1));
+        // Extinct species stay at equilibrium
+        let extinct = history(&[[100, 0], [100, 0]]);
+        assert!(is_at_equilibrium(&extinct, 0.0));
+    }
+}